@@ -0,0 +1,214 @@
+use std::collections::BTreeSet;
+
+/// What can be found out about a ROM without running it.
+/// The program is followed from its start like the chip would run it,
+/// everything that is never reached that way is taken as data.
+#[derive(Debug, Clone)]
+pub struct Analysis {
+    /// The extension the ROM was most likely written for
+    pub platform: Guess,
+    /// Address and opcode of the instructions that decided the guess
+    pub evidence: Vec<(u16, u16)>,
+    /// Ranges of addresses (inclusive) that are run as instructions
+    pub code: Vec<(u16, u16)>,
+    /// Ranges of addresses (inclusive) that are never run
+    pub data: Vec<(u16, u16)>,
+    /// Instructions that write to memory where there is code
+    pub self_modifying: Vec<(u16, u16)>,
+    /// Computed jumps (`Bnnn`), which can go places that can't be followed
+    pub computed_jumps: Vec<u16>,
+}
+
+/// The extensions of Chip-8, by the instructions they brought
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Guess {
+    Chip8,
+    /// Calls machine code routines of the COSMAC VIP with `0nnn`
+    Vip,
+    /// The colors, second keypad and `5xy1` of the VIP with the color board
+    Chip8x,
+    Schip,
+    /// SUPER-CHIP with the 256x192 color mode
+    Megachip,
+    XoChip,
+}
+
+impl Guess {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Guess::Chip8 => "CHIP-8",
+            Guess::Vip => "CHIP-8 with machine code (COSMAC VIP)",
+            Guess::Chip8x => "CHIP-8X",
+            Guess::Schip => "SUPER-CHIP",
+            Guess::Megachip => "MEGA-CHIP",
+            Guess::XoChip => "XO-CHIP",
+        }
+    }
+}
+
+/// The extension an opcode belongs to, if it isn't plain Chip-8
+fn extension_of(opcode: u16) -> Option<Guess> {
+    let x = (opcode & 0x0F00) >> 8;
+    let y = (opcode & 0x00F0) >> 4;
+    let n = opcode & 0x000F;
+
+    let op_1 = (opcode & 0xF000) >> 12;
+    match (op_1, x, y, n) {
+        (0, 0, 0xE, 0) | (0, 0, 0xE, 0xE) => None,
+        // scroll up
+        (0, 0, 0xD, _) => Some(Guess::XoChip),
+        // scroll down, scroll right/left, exit, lores and hires
+        (0, 0, 0xC, _) | (0, 0, 0xF, 0xB..=0xF) => Some(Guess::Schip),
+        // next background color
+        (0, 2, 0xA, 0) => Some(Guess::Chip8x),
+        // MEGA-CHIP mode off and on, long I.
+        // Its other instructions (`02nn` to `09nn`) look just like calls of machine code,
+        // but every MEGA-CHIP rom turns the mode on first
+        (0, 0, 1, 0) | (0, 0, 1, 1) | (0, 1, _, _) => Some(Guess::Megachip),
+        (0, _, _, _) => Some(Guess::Vip),
+        // add the nibbles on their own
+        (0x5, _, _, 0x1) => Some(Guess::Chip8x),
+        // save and load a range of registers
+        (0x5, _, _, 0x2) | (0x5, _, _, 0x3) => Some(Guess::XoChip),
+        // skip on the keys of the second keypad, `Bxyn` can't be told apart from a jump
+        (0xE, _, 0xF, 0x2) | (0xE, _, 0xF, 0x5) => Some(Guess::Chip8x),
+        // 16x16 sprites
+        (0xD, _, _, 0x0) => Some(Guess::Schip),
+        // long I, planes, audio pattern and pitch
+        (0xF, 0, 0, 0) | (0xF, _, 0x0, 0x1) | (0xF, 0, 0x0, 0x2) | (0xF, _, 0x3, 0xA) => Some(Guess::XoChip),
+        // big font, flags registers
+        (0xF, _, 0x3, 0x0) | (0xF, _, 0x7, 0x5) | (0xF, _, 0x8, 0x5) => Some(Guess::Schip),
+        _ => None,
+    }
+}
+
+/// `F000 nnnn` of XO-CHIP and `01nn nnnn` of MEGA-CHIP are the only instructions with 4 bytes
+fn is_long(opcode: u16) -> bool {
+    opcode == 0xF000 || opcode & 0xFF00 == 0x0100
+}
+
+/// Look at a program that is loaded at `start`
+pub fn analyze(program: &[u8], start: u16) -> Analysis {
+    let start = start as usize;
+    let end = start + program.len();
+    let word = |address: usize| -> Option<u16> {
+        if address < start || address + 1 >= end {
+            return None;
+        }
+        let offset = address - start;
+        Some((program[offset] as u16) << 8 | program[offset + 1] as u16)
+    };
+
+    let mut is_code = vec![false; program.len()];
+    let mut visited = BTreeSet::new();
+    let mut platform = Guess::Chip8;
+    let mut evidence = Vec::new();
+    let mut writes = Vec::new();
+    let mut computed_jumps = Vec::new();
+
+    // Addresses still to follow, together with what I was set to on the way there
+    let mut todo: Vec<(usize, Option<usize>)> = vec![(start, None)];
+    while let Some((address, mut i)) = todo.pop() {
+        if !visited.insert(address) {
+            continue;
+        }
+        let Some(opcode) = word(address) else { continue };
+        let long = is_long(opcode);
+        let size = if long { 4 } else { 2 };
+        for offset in address..(address + size).min(end) {
+            is_code[offset - start] = true;
+        }
+
+        if let Some(extension) = extension_of(opcode) {
+            if extension > platform {
+                platform = extension;
+                evidence.clear();
+            }
+            if extension == platform && evidence.len() < 4 {
+                evidence.push((address as u16, opcode));
+            }
+        }
+
+        let x = ((opcode & 0x0F00) >> 8) as usize;
+        let nnn = (opcode & 0x0FFF) as usize;
+        let next = address + size;
+        // A skip jumps over the next instruction, which can be a long one
+        let skipped = next + if word(next).is_some_and(is_long) { 4 } else { 2 };
+
+        let op_1 = (opcode & 0xF000) >> 12;
+        let op_4 = opcode & 0x000F;
+        match (op_1, opcode & 0x00FF) {
+            // return and exit end the path
+            (0x0, _) if opcode == 0x00EE || opcode == 0x00FD => continue,
+            (0x1, _) => {
+                todo.push((nnn, i));
+                continue;
+            },
+            (0x2, _) => todo.push((nnn, i)),
+            (0x3, _) | (0x4, _) | (0x9, _) | (0xE, 0x9E) | (0xE, 0xA1) => todo.push((skipped, i)),
+            // The skips on the second keypad of CHIP-8X
+            (0xE, 0xF2) | (0xE, 0xF5) => todo.push((skipped, i)),
+            (0x5, _) if op_4 == 0 => todo.push((skipped, i)),
+            (0xA, _) => i = Some(nnn),
+            (0xB, _) => {
+                // At least the table the jump goes into is code
+                computed_jumps.push(address as u16);
+                todo.push((nnn, i));
+                continue;
+            },
+            (0x0, kk) if long => i = word(address + 2).map(|target| (kk as usize) << 16 | target as usize),
+            (0xF, 0x00) if long => i = word(address + 2).map(|target| target as usize),
+            (0xF, 0x1E) | (0xF, 0x29) | (0xF, 0x30) => i = None,
+            (0xF, 0x33) => {
+                if let Some(i) = i {
+                    writes.push((address, i, 3));
+                }
+            },
+            (0xF, 0x55) => {
+                if let Some(i) = i {
+                    writes.push((address, i, x + 1));
+                }
+                // Where I ends up depends on the load/store quirk
+                i = None;
+            },
+            (0xF, 0x65) => i = None,
+            _ => (),
+        }
+        todo.push((next, i));
+    }
+
+    let self_modifying = writes
+        .into_iter()
+        .filter_map(|(address, i, len)| {
+            let hit = (i..i + len).find(|target| (start..end).contains(target) && is_code[target - start])?;
+            Some((address as u16, hit as u16))
+        })
+        .collect();
+
+    Analysis {
+        platform,
+        evidence,
+        code: ranges(&is_code, start, true),
+        data: ranges(&is_code, start, false),
+        self_modifying,
+        computed_jumps,
+    }
+}
+
+/// The ranges of addresses where `is_code` is `code`
+fn ranges(is_code: &[bool], start: usize, code: bool) -> Vec<(u16, u16)> {
+    let mut ranges = Vec::new();
+    let mut from = None;
+    // One past the end closes the last range
+    for (offset, value) in is_code.iter().copied().chain([!code]).enumerate() {
+        match (from, value == code) {
+            (None, true) => from = Some(offset),
+            (Some(first), false) => {
+                ranges.push(((start + first) as u16, (start + offset - 1) as u16));
+                from = None;
+            },
+            _ => (),
+        }
+    }
+    ranges
+}
@@ -0,0 +1,873 @@
+use std::collections::{HashMap, VecDeque};
+
+/// Assemble the Octo source of a program, the way Octo programs are written:
+/// <https://github.com/JohnEarnest/Octo/blob/gh-pages/docs/Manual.md>
+///
+/// Most of the language is there: labels, `:const`, `:alias`, `:calc`, `:macro`,
+/// `:byte`, `:pointer`, `:org`, `:next`, `:unpack`, the Chip-8, SUPER-CHIP and XO-CHIP
+/// instructions and the `if`/`loop` structures, comparisons with `<` and friends included.
+/// Strings (`:stringmode`) and `:assert` are not.
+pub fn assemble(source: &str) -> Result<Vec<u8>, String> {
+    let mut assembler = Assembler::new(source);
+    assembler.run().map_err(|e| format!("line {}: {}", assembler.line, e))?;
+    Ok(assembler.memory)
+}
+
+/// Programs start at 0x200, like all Chip-8 programs
+const START: usize = 0x200;
+
+#[derive(Debug, Clone)]
+struct Token {
+    text: String,
+    line: usize,
+}
+
+#[derive(Debug, Clone)]
+struct Macro {
+    args: Vec<String>,
+    body: Vec<Token>,
+}
+
+/// How a label that isn't defined yet goes into the code once it is
+#[derive(Debug, Clone, Copy)]
+enum Patch {
+    /// The nnn of an instruction
+    Address,
+    /// Two bytes, for `i := long` and `:pointer`
+    Long,
+    /// `:unpack`: the high nibble of the address into the low nibble of the
+    /// instruction at the address, the low byte into the next instruction
+    Unpack,
+    /// `:unpack long`: the high byte into the instruction at the address, the low byte into the next one
+    UnpackLong,
+}
+
+#[derive(Debug, Clone)]
+struct Fixup {
+    at: usize,
+    label: String,
+    patch: Patch,
+    line: usize,
+}
+
+/// A value that is known, or a label that comes later
+#[derive(Debug, Clone)]
+enum Value {
+    Known(i64),
+    Label(String),
+}
+
+/// A condition of `if` and `while`
+struct Condition {
+    /// Instructions that have to run before the skip
+    prelude: Vec<u16>,
+    /// Skips the next instruction if the condition is true
+    skip_if_true: u16,
+    /// Skips the next instruction if the condition is false
+    skip_if_false: u16,
+}
+
+struct Loop {
+    start: usize,
+    /// The jumps of the `while`s, they go behind the `again`
+    exits: Vec<usize>,
+}
+
+struct Assembler {
+    tokens: VecDeque<Token>,
+    /// Everything from 0x200 on
+    memory: Vec<u8>,
+    here: usize,
+    line: usize,
+    labels: HashMap<String, usize>,
+    constants: HashMap<String, i64>,
+    aliases: HashMap<String, u16>,
+    macros: HashMap<String, Macro>,
+    fixups: Vec<Fixup>,
+    /// The jumps of `begin` and `else` that still need to know where they go
+    branches: Vec<usize>,
+    loops: Vec<Loop>,
+}
+
+impl Assembler {
+    fn new(source: &str) -> Assembler {
+        let mut tokens = VecDeque::new();
+        for (number, line) in source.lines().enumerate() {
+            for text in line.split_whitespace() {
+                // The rest of the line is a comment
+                if text.starts_with('#') {
+                    break;
+                }
+                tokens.push_back(Token { text: text.to_string(), line: number + 1 });
+            }
+        }
+        Assembler {
+            tokens,
+            memory: Vec::new(),
+            here: START,
+            line: 0,
+            labels: HashMap::new(),
+            constants: HashMap::new(),
+            aliases: HashMap::new(),
+            macros: HashMap::new(),
+            fixups: Vec::new(),
+            branches: Vec::new(),
+            loops: Vec::new(),
+        }
+    }
+
+    fn run(&mut self) -> Result<(), String> {
+        // The program starts with a jump to main
+        self.emit(0x1000);
+        while !self.tokens.is_empty() {
+            self.statement()?;
+        }
+        if !self.branches.is_empty() {
+            return Err("an if ... begin is missing its end".to_string());
+        }
+        if !self.loops.is_empty() {
+            return Err("a loop is missing its again".to_string());
+        }
+
+        let main = *self.labels.get("main").ok_or("the program has no main label")?;
+        self.put(START, 0x1000 | main as u16);
+        for fixup in std::mem::take(&mut self.fixups) {
+            self.line = fixup.line;
+            let address = *self.labels.get(&fixup.label).ok_or_else(|| format!("{} is never defined", fixup.label))?;
+            self.patch(fixup.at, address, fixup.patch)?;
+        }
+        Ok(())
+    }
+
+    fn next(&mut self) -> Result<String, String> {
+        let token = self.tokens.pop_front().ok_or("the program ends in the middle of a statement")?;
+        self.line = token.line;
+        Ok(token.text)
+    }
+
+    fn expect(&mut self, text: &str) -> Result<(), String> {
+        match self.next()? {
+            token if token == text => Ok(()),
+            token => Err(format!("expected {}, not {}", text, token)),
+        }
+    }
+
+    fn peek(&self) -> Option<&str> {
+        self.tokens.front().map(|token| token.text.as_str())
+    }
+
+    fn statement(&mut self) -> Result<(), String> {
+        let token = self.next()?;
+        match token.as_str() {
+            ":" => {
+                let name = self.next()?;
+                self.define(name, self.here)?;
+            },
+            ":alias" => {
+                let name = self.next()?;
+                let register = match self.peek() {
+                    Some("{") => {
+                        self.next()?;
+                        self.calc()? as u16
+                    },
+                    _ => {
+                        let token = self.next()?;
+                        self.register(&token)?
+                    },
+                };
+                if register > 0xF {
+                    return Err(format!("there is no register {}", register));
+                }
+                self.aliases.insert(name, register);
+            },
+            ":const" => {
+                let name = self.next()?;
+                let token = self.next()?;
+                let value = self.known(&token)?;
+                self.constants.insert(name, value);
+            },
+            ":calc" => {
+                let name = self.next()?;
+                self.expect("{")?;
+                let value = self.calc()?;
+                self.constants.insert(name, value);
+            },
+            ":byte" => {
+                let token = self.next()?;
+                let value = self.known(&token)?;
+                self.emit_byte(value)?;
+            },
+            ":pointer" => {
+                let token = self.next()?;
+                let value = self.value(&token)?;
+                self.emit(0);
+                self.resolve(self.here - 2, value, Patch::Long)?;
+            },
+            ":org" => {
+                let token = self.next()?;
+                let address = self.known(&token)?;
+                if !(START as i64..=0xFFFF).contains(&address) {
+                    return Err(format!("can't put code at {:#X}", address));
+                }
+                self.here = address as usize;
+            },
+            ":next" => {
+                // A label for the second byte of the next instruction, to change it while running
+                let name = self.next()?;
+                self.define(name, self.here + 1)?;
+                self.statement()?;
+            },
+            ":unpack" => {
+                let token = self.next()?;
+                let label = self.next()?;
+                let value = self.value(&label)?;
+                if token == "long" {
+                    self.emit(0x6000);
+                    self.emit(0x6100);
+                    self.resolve(self.here - 4, value, Patch::UnpackLong)?;
+                } else {
+                    let nibble = self.known(&token)?;
+                    self.emit(0x6000 | ((nibble as u16 & 0xF) << 4));
+                    self.emit(0x6100);
+                    self.resolve(self.here - 4, value, Patch::Unpack)?;
+                }
+            },
+            ":call" => {
+                let token = self.next()?;
+                self.address_op(0x2000, &token)?;
+            },
+            ":macro" => {
+                let name = self.next()?;
+                let mut args = Vec::new();
+                loop {
+                    match self.next()? {
+                        open if open == "{" => break,
+                        arg => args.push(arg),
+                    }
+                }
+                let body = self.block()?;
+                self.macros.insert(name, Macro { args, body });
+            },
+            ":proto" | ":breakpoint" => {
+                self.next()?;
+            },
+            ":monitor" => {
+                self.next()?;
+                self.next()?;
+            },
+            ":assert" | ":stringmode" => return Err(format!("{} is not supported", token)),
+            ";" | "return" => self.emit(0x00EE),
+            "clear" => self.emit(0x00E0),
+            "hires" | "bighires" => self.emit(0x00FF),
+            "lores" => self.emit(0x00FE),
+            "exit" => self.emit(0x00FD),
+            "scroll-left" => self.emit(0x00FC),
+            "scroll-right" => self.emit(0x00FB),
+            "scroll-down" => {
+                let n = self.nibble()?;
+                self.emit(0x00C0 | n);
+            },
+            "scroll-up" => {
+                let n = self.nibble()?;
+                self.emit(0x00D0 | n);
+            },
+            "audio" => self.emit(0xF002),
+            "jump" => {
+                let token = self.next()?;
+                self.address_op(0x1000, &token)?;
+            },
+            "jump0" => {
+                let token = self.next()?;
+                self.address_op(0xB000, &token)?;
+            },
+            "native" => {
+                let token = self.next()?;
+                self.address_op(0x0000, &token)?;
+            },
+            "sprite" => {
+                let x = self.next_register()?;
+                let y = self.next_register()?;
+                let n = self.nibble()?;
+                self.emit(0xD000 | x << 8 | y << 4 | n);
+            },
+            "bcd" => {
+                let x = self.next_register()?;
+                self.emit(0xF033 | x << 8);
+            },
+            "save" | "load" => {
+                let x = self.next_register()?;
+                let store = token == "save";
+                if self.peek() == Some("-") {
+                    self.next()?;
+                    let y = self.next_register()?;
+                    self.emit(if store { 0x5002 } else { 0x5003 } | x << 8 | y << 4);
+                } else {
+                    self.emit(if store { 0xF055 } else { 0xF065 } | x << 8);
+                }
+            },
+            "saveflags" => {
+                let x = self.next_register()?;
+                self.emit(0xF075 | x << 8);
+            },
+            "loadflags" => {
+                let x = self.next_register()?;
+                self.emit(0xF085 | x << 8);
+            },
+            "plane" => {
+                let n = self.nibble()?;
+                self.emit(0xF001 | n << 8);
+            },
+            "delay" | "buzzer" | "pitch" => {
+                self.expect(":=")?;
+                let x = self.next_register()?;
+                let op = match token.as_str() {
+                    "delay" => 0xF015,
+                    "buzzer" => 0xF018,
+                    _ => 0xF03A,
+                };
+                self.emit(op | x << 8);
+            },
+            "i" => self.i()?,
+            "if" => {
+                let condition = self.condition()?;
+                for op in &condition.prelude {
+                    self.emit(*op);
+                }
+                match self.next()?.as_str() {
+                    // The statement after `then` is skipped if the condition is false
+                    "then" => self.emit(condition.skip_if_false),
+                    "begin" => {
+                        self.emit(condition.skip_if_true);
+                        self.branches.push(self.here);
+                        self.emit(0x1000);
+                    },
+                    other => return Err(format!("expected then or begin, not {}", other)),
+                }
+            },
+            "else" => {
+                let branch = self.branches.pop().ok_or("else without if ... begin")?;
+                self.branches.push(self.here);
+                self.emit(0x1000);
+                self.put(branch, 0x1000 | self.here as u16);
+            },
+            "end" => {
+                let branch = self.branches.pop().ok_or("end without if ... begin")?;
+                self.put(branch, 0x1000 | self.here as u16);
+            },
+            "loop" => self.loops.push(Loop { start: self.here, exits: Vec::new() }),
+            "while" => {
+                let condition = self.condition()?;
+                for op in &condition.prelude {
+                    self.emit(*op);
+                }
+                self.emit(condition.skip_if_true);
+                let exit = self.here;
+                self.loops.last_mut().ok_or("while outside of a loop")?.exits.push(exit);
+                self.emit(0x1000);
+            },
+            "again" => {
+                let repeat = self.loops.pop().ok_or("again without loop")?;
+                self.emit(0x1000 | repeat.start as u16);
+                for exit in repeat.exits {
+                    self.put(exit, 0x1000 | self.here as u16);
+                }
+            },
+            _ if self.macros.contains_key(&token) => self.expand(&token)?,
+            _ if self.is_register(&token) => self.register_op(&token)?,
+            _ => {
+                // A number on its own is a byte, a name on its own calls it
+                match self.value(&token)? {
+                    Value::Known(value) if self.labels.contains_key(&token) => self.address(0x2000, Value::Known(value))?,
+                    Value::Known(value) => self.emit_byte(value)?,
+                    label => self.address(0x2000, label)?,
+                }
+            },
+        }
+        Ok(())
+    }
+
+    /// The tokens up to the matching `}`
+    fn block(&mut self) -> Result<Vec<Token>, String> {
+        let mut depth = 1;
+        let mut body = Vec::new();
+        loop {
+            let token = self.tokens.pop_front().ok_or("a { is never closed")?;
+            match token.text.as_str() {
+                "{" => depth += 1,
+                "}" => depth -= 1,
+                _ => (),
+            }
+            if depth == 0 {
+                return Ok(body);
+            }
+            body.push(token);
+        }
+    }
+
+    /// Put the body of a macro in place of its name, with the arguments filled in
+    fn expand(&mut self, name: &str) -> Result<(), String> {
+        let Macro { args, body } = self.macros[name].clone();
+        let mut values = HashMap::new();
+        for arg in args {
+            values.insert(arg, self.next()?);
+        }
+        for token in body.into_iter().rev() {
+            let text = values.get(&token.text).cloned().unwrap_or(token.text);
+            self.tokens.push_front(Token { text, line: self.line });
+        }
+        Ok(())
+    }
+
+    fn i(&mut self) -> Result<(), String> {
+        match self.next()?.as_str() {
+            ":=" => {
+                let token = self.next()?;
+                match token.as_str() {
+                    "hex" => {
+                        let x = self.next_register()?;
+                        self.emit(0xF029 | x << 8);
+                    },
+                    "bighex" => {
+                        let x = self.next_register()?;
+                        self.emit(0xF030 | x << 8);
+                    },
+                    "long" => {
+                        let token = self.next()?;
+                        let value = self.value(&token)?;
+                        self.emit(0xF000);
+                        self.emit(0);
+                        self.resolve(self.here - 2, value, Patch::Long)?;
+                    },
+                    _ => self.address_op(0xA000, &token)?,
+                }
+            },
+            "+=" => {
+                let x = self.next_register()?;
+                self.emit(0xF01E | x << 8);
+            },
+            other => return Err(format!("i can't {}", other)),
+        }
+        Ok(())
+    }
+
+    fn register_op(&mut self, token: &str) -> Result<(), String> {
+        let x = self.register(token)?;
+        let op = self.next()?;
+        let rhs = self.next()?;
+        if op == ":=" {
+            match rhs.as_str() {
+                "random" => {
+                    let token = self.next()?;
+                    let mask = self.byte(&token)?;
+                    self.emit(0xC000 | x << 8 | mask);
+                },
+                "delay" => self.emit(0xF007 | x << 8),
+                "key" => self.emit(0xF00A | x << 8),
+                _ if self.is_register(&rhs) => {
+                    let y = self.register(&rhs)?;
+                    self.emit(0x8000 | x << 8 | y << 4);
+                },
+                _ => {
+                    let value = self.byte(&rhs)?;
+                    self.emit(0x6000 | x << 8 | value);
+                },
+            }
+            return Ok(());
+        }
+
+        if self.is_register(&rhs) {
+            let y = self.register(&rhs)?;
+            let n = match op.as_str() {
+                "|=" => 0x1,
+                "&=" => 0x2,
+                "^=" => 0x3,
+                "+=" => 0x4,
+                "-=" => 0x5,
+                ">>=" => 0x6,
+                "=-" => 0x7,
+                "<<=" => 0xE,
+                other => return Err(format!("unknown operator {}", other)),
+            };
+            self.emit(0x8000 | x << 8 | y << 4 | n);
+        } else {
+            let value = self.byte(&rhs)?;
+            match op.as_str() {
+                "+=" => self.emit(0x7000 | x << 8 | value),
+                "-=" => self.emit(0x7000 | x << 8 | (value as u8).wrapping_neg() as u16),
+                other => return Err(format!("{} needs a register on the right", other)),
+            }
+        }
+        Ok(())
+    }
+
+    fn condition(&mut self) -> Result<Condition, String> {
+        let x = self.next_register()?;
+        let op = self.next()?;
+        match op.as_str() {
+            "key" => return Ok(Condition { prelude: Vec::new(), skip_if_true: 0xE09E | x << 8, skip_if_false: 0xE0A1 | x << 8 }),
+            "-key" => return Ok(Condition { prelude: Vec::new(), skip_if_true: 0xE0A1 | x << 8, skip_if_false: 0xE09E | x << 8 }),
+            _ => (),
+        }
+
+        let rhs = self.next()?;
+        let y = if self.is_register(&rhs) { Some(self.register(&rhs)?) } else { None };
+        let kk = match y {
+            Some(_) => 0,
+            None => self.byte(&rhs)?,
+        };
+        let (equal, not_equal) = match y {
+            Some(y) => (0x5000 | x << 8 | y << 4, 0x9000 | x << 8 | y << 4),
+            None => (0x3000 | x << 8 | kk, 0x4000 | x << 8 | kk),
+        };
+        match op.as_str() {
+            "==" => return Ok(Condition { prelude: Vec::new(), skip_if_true: equal, skip_if_false: not_equal }),
+            "!=" => return Ok(Condition { prelude: Vec::new(), skip_if_true: not_equal, skip_if_false: equal }),
+            _ => (),
+        }
+
+        // The other comparisons go through VF, which ends up 1 when no borrow happens:
+        // vf := x, vf -= y gives x >= y, vf := y, vf -= x gives y >= x
+        let (swapped, when) = match op.as_str() {
+            ">=" => (false, 1),
+            "<" => (false, 0),
+            "<=" => (true, 1),
+            ">" => (true, 0),
+            other => return Err(format!("unknown comparison {}", other)),
+        };
+        let prelude = match (y, swapped) {
+            (Some(y), false) => vec![0x8F00 | x << 4, 0x8F05 | y << 4],
+            (Some(y), true) => vec![0x8F00 | y << 4, 0x8F05 | x << 4],
+            // vf := kk, vf =- x
+            (None, false) => vec![0x6F00 | kk, 0x8F07 | x << 4],
+            (None, true) => vec![0x6F00 | kk, 0x8F05 | x << 4],
+        };
+        Ok(Condition { prelude, skip_if_true: 0x3F00 | when, skip_if_false: 0x4F00 | when })
+    }
+
+    /// Evaluate a `:calc` expression up to the closing `}`.
+    /// Like in Octo, the operators have no precedence and go from right to left.
+    fn calc(&mut self) -> Result<i64, String> {
+        let tokens: Vec<String> = self.block()?.into_iter().map(|token| token.text).collect();
+        let mut position = 0;
+        let value = self.expression(&tokens, &mut position)?;
+        if position != tokens.len() {
+            return Err(format!("unexpected {} in the expression", tokens[position]));
+        }
+        Ok(value.round() as i64)
+    }
+
+    fn expression(&self, tokens: &[String], position: &mut usize) -> Result<f64, String> {
+        let left = self.term(tokens, position)?;
+        let Some(op) = tokens.get(*position) else { return Ok(left) };
+        if op == ")" {
+            return Ok(left);
+        }
+        *position += 1;
+        let right = self.expression(tokens, position)?;
+        let (l, r) = (left as i64, right as i64);
+        Ok(match op.as_str() {
+            "+" => left + right,
+            "-" => left - right,
+            "*" => left * right,
+            "/" => left / right,
+            "%" => left % right,
+            "&" => (l & r) as f64,
+            "|" => (l | r) as f64,
+            "^" => (l ^ r) as f64,
+            "<<" => (l << r) as f64,
+            ">>" => (l >> r) as f64,
+            "min" => left.min(right),
+            "max" => left.max(right),
+            "pow" => left.powf(right),
+            "<" => (left < right) as i64 as f64,
+            ">" => (left > right) as i64 as f64,
+            "<=" => (left <= right) as i64 as f64,
+            ">=" => (left >= right) as i64 as f64,
+            "==" => (left == right) as i64 as f64,
+            "!=" => (left != right) as i64 as f64,
+            other => return Err(format!("unknown operator {}", other)),
+        })
+    }
+
+    fn term(&self, tokens: &[String], position: &mut usize) -> Result<f64, String> {
+        let token = tokens.get(*position).ok_or("the expression ends too early")?;
+        *position += 1;
+        match token.as_str() {
+            "(" => {
+                let value = self.expression(tokens, position)?;
+                match tokens.get(*position) {
+                    Some(close) if close == ")" => *position += 1,
+                    _ => return Err("a ( is never closed".to_string()),
+                }
+                Ok(value)
+            },
+            "-" => Ok(-self.term(tokens, position)?),
+            "~" => Ok(!(self.term(tokens, position)? as i64) as f64),
+            "!" => Ok((self.term(tokens, position)? == 0.0) as i64 as f64),
+            "floor" => Ok(self.term(tokens, position)?.floor()),
+            "ceil" => Ok(self.term(tokens, position)?.ceil()),
+            "abs" => Ok(self.term(tokens, position)?.abs()),
+            "sqrt" => Ok(self.term(tokens, position)?.sqrt()),
+            "sin" => Ok(self.term(tokens, position)?.sin()),
+            "cos" => Ok(self.term(tokens, position)?.cos()),
+            "HERE" => Ok(self.here as f64),
+            "PI" => Ok(std::f64::consts::PI),
+            "E" => Ok(std::f64::consts::E),
+            _ => match self.lookup(token) {
+                Some(value) => Ok(value as f64),
+                None => Err(format!("{} has to be defined before it is used in a calculation", token)),
+            },
+        }
+    }
+
+    fn define(&mut self, name: String, address: usize) -> Result<(), String> {
+        if self.labels.insert(name.clone(), address).is_some() {
+            return Err(format!("{} is defined twice", name));
+        }
+        Ok(())
+    }
+
+    fn is_register(&self, token: &str) -> bool {
+        self.register(token).is_ok()
+    }
+
+    fn register(&self, token: &str) -> Result<u16, String> {
+        if let Some(register) = self.aliases.get(token) {
+            return Ok(*register);
+        }
+        let mut chars = token.chars();
+        match (chars.next(), chars.next(), chars.next()) {
+            (Some('v' | 'V'), Some(digit), None) => digit
+                .to_digit(16)
+                .map(|register| register as u16)
+                .ok_or_else(|| format!("{} is not a register", token)),
+            _ => Err(format!("{} is not a register", token)),
+        }
+    }
+
+    fn next_register(&mut self) -> Result<u16, String> {
+        let token = self.next()?;
+        self.register(&token)
+    }
+
+    /// A number, constant or label, labels can come later
+    fn value(&mut self, token: &str) -> Result<Value, String> {
+        if token == "{" {
+            return self.calc().map(Value::Known);
+        }
+        if let Some(value) = self.lookup(token) {
+            return Ok(Value::Known(value));
+        }
+        if self.is_register(token) || token.starts_with(':') {
+            return Err(format!("expected a value, not {}", token));
+        }
+        Ok(Value::Label(token.to_string()))
+    }
+
+    /// A number, constant or label that is already defined
+    fn lookup(&self, token: &str) -> Option<i64> {
+        parse_number(token)
+            .or_else(|| self.constants.get(token).copied())
+            .or_else(|| self.labels.get(token).map(|address| *address as i64))
+    }
+
+    /// A value that has to be known right away
+    fn known(&mut self, token: &str) -> Result<i64, String> {
+        match self.value(token)? {
+            Value::Known(value) => Ok(value),
+            Value::Label(name) => Err(format!("{} is not defined", name)),
+        }
+    }
+
+    fn byte(&mut self, token: &str) -> Result<u16, String> {
+        let value = self.known(token)?;
+        if !(-128..=255).contains(&value) {
+            return Err(format!("{} does not fit in a byte", value));
+        }
+        Ok(value as u8 as u16)
+    }
+
+    fn nibble(&mut self) -> Result<u16, String> {
+        let token = self.next()?;
+        let value = self.known(&token)?;
+        if !(0..=15).contains(&value) {
+            return Err(format!("{} does not fit in a nibble", value));
+        }
+        Ok(value as u16)
+    }
+
+    /// An instruction with an address in the lower 12 bits
+    fn address_op(&mut self, op: u16, token: &str) -> Result<(), String> {
+        let value = self.value(token)?;
+        self.address(op, value)
+    }
+
+    fn address(&mut self, op: u16, value: Value) -> Result<(), String> {
+        self.emit(op);
+        self.resolve(self.here - 2, value, Patch::Address)
+    }
+
+    /// Patch a value in right away or once the label is defined
+    fn resolve(&mut self, at: usize, value: Value, patch: Patch) -> Result<(), String> {
+        match value {
+            Value::Known(value) => self.patch(at, value as usize, patch),
+            Value::Label(label) => {
+                self.fixups.push(Fixup { at, label, patch, line: self.line });
+                Ok(())
+            },
+        }
+    }
+
+    fn patch(&mut self, at: usize, value: usize, patch: Patch) -> Result<(), String> {
+        match patch {
+            Patch::Address => {
+                if value > 0xFFF {
+                    return Err(format!("{:#X} is too far away, use i := long", value));
+                }
+                let op = self.get(at) & 0xF000 | value as u16;
+                self.put(at, op);
+            },
+            Patch::Long => self.put(at, value as u16),
+            Patch::Unpack => {
+                let op = self.get(at) | (value as u16 >> 8) & 0xF;
+                self.put(at, op);
+                self.put(at + 2, 0x6100 | value as u16 & 0xFF);
+            },
+            Patch::UnpackLong => {
+                self.put(at, 0x6000 | value as u16 >> 8);
+                self.put(at + 2, 0x6100 | value as u16 & 0xFF);
+            },
+        }
+        Ok(())
+    }
+
+    fn get(&self, address: usize) -> u16 {
+        let offset = address - START;
+        (self.memory[offset] as u16) << 8 | self.memory[offset + 1] as u16
+    }
+
+    fn put(&mut self, address: usize, op: u16) {
+        let offset = address - START;
+        if self.memory.len() < offset + 2 {
+            self.memory.resize(offset + 2, 0);
+        }
+        self.memory[offset..offset + 2].copy_from_slice(&op.to_be_bytes());
+    }
+
+    fn emit(&mut self, op: u16) {
+        self.put(self.here, op);
+        self.here += 2;
+    }
+
+    fn emit_byte(&mut self, value: i64) -> Result<(), String> {
+        if !(-128..=255).contains(&value) {
+            return Err(format!("{} does not fit in a byte", value));
+        }
+        let offset = self.here - START;
+        if self.memory.len() < offset + 1 {
+            self.memory.resize(offset + 1, 0);
+        }
+        self.memory[offset] = value as u8;
+        self.here += 1;
+        Ok(())
+    }
+}
+
+/// Decimal, 0x hexadecimal or 0b binary, with an optional minus
+fn parse_number(text: &str) -> Option<i64> {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, text),
+    };
+    let value = if let Some(hex) = digits.strip_prefix("0x").or_else(|| digits.strip_prefix("0X")) {
+        i64::from_str_radix(hex, 16).ok()?
+    } else if let Some(binary) = digits.strip_prefix("0b").or_else(|| digits.strip_prefix("0B")) {
+        i64::from_str_radix(binary, 2).ok()?
+    } else {
+        digits.parse::<i64>().ok()?
+    };
+    Some(if negative { -value } else { value })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(source: &str) -> Vec<u8> {
+        assemble(source).unwrap()
+    }
+
+    fn error(source: &str) -> String {
+        assemble(source).unwrap_err()
+    }
+
+    #[test]
+    fn labels() {
+        // The jump to main comes first, sub is called before it is defined
+        let program = words(": main sub jump main : sub return");
+        assert_eq!(program, [0x12, 0x02, 0x22, 0x06, 0x12, 0x02, 0x00, 0xEE]);
+    }
+
+    #[test]
+    fn constants() {
+        let program = words(":const SPEED 3 : main v1 := SPEED v2 += SPEED");
+        assert_eq!(program, [0x12, 0x02, 0x61, 0x03, 0x72, 0x03]);
+    }
+
+    #[test]
+    fn if_then_skips_when_false() {
+        assert_eq!(words(": main if v0 == 5 then v1 := 2"), [0x12, 0x02, 0x40, 0x05, 0x61, 0x02]);
+        assert_eq!(words(": main if v0 != 5 then v1 := 2"), [0x12, 0x02, 0x30, 0x05, 0x61, 0x02]);
+        assert_eq!(words(": main if v0 == v3 then v1 := 2"), [0x12, 0x02, 0x90, 0x30, 0x61, 0x02]);
+    }
+
+    #[test]
+    fn begin_else_end() {
+        let program = words(": main if v0 == 5 begin v1 := 1 else v1 := 2 end");
+        assert_eq!(program, [
+            0x12, 0x02,
+            // skip the jump to the else part when the condition holds
+            0x30, 0x05, 0x12, 0x0A,
+            0x61, 0x01, 0x12, 0x0C,
+            0x61, 0x02,
+        ]);
+    }
+
+    #[test]
+    fn loops() {
+        let program = words(": main loop v0 += 1 while v0 != 8 again");
+        assert_eq!(program, [0x12, 0x02, 0x70, 0x01, 0x40, 0x08, 0x12, 0x0A, 0x12, 0x02]);
+    }
+
+    #[test]
+    fn bytes() {
+        assert_eq!(words(": main :byte 0xAB :byte 255 :byte -1 :byte 0b101"), [0x12, 0x02, 0xAB, 0xFF, 0xFF, 0x05]);
+    }
+
+    #[test]
+    fn runs_on_the_chip() {
+        let program = words(": main i := 0x300 v0 := 5 i += v0");
+        let mut chip = crate::Cpu::new();
+        // Anything the chip doesn't know stops it
+        chip.unknown_opcodes = crate::UnknownOpcodes::Halt;
+        chip.load_program(&program).unwrap();
+        // The jump to main, then the three instructions
+        for _ in 0..4 {
+            chip.execute_cycle();
+        }
+        assert_eq!(chip.i, 0x305);
+        assert_eq!(chip.state, crate::State::Running);
+    }
+
+    #[test]
+    fn errors() {
+        assert!(error("v0 := 1").contains("the program has no main label"));
+        assert!(error(": main jump nowhere").contains("nowhere is never defined"));
+        assert!(error(": main :byte 256").contains("256 does not fit in a byte"));
+        assert!(error(": main if v0 == 1 begin v1 := 2").contains("an if ... begin is missing its end"));
+        assert!(error(": main else").contains("else without if ... begin"));
+        assert!(error(": main loop v0 += 1").contains("a loop is missing its again"));
+        assert!(error(": main : main").contains("main is defined twice"));
+        assert_eq!(error(": main\nv0 := 1\n:byte 300"), "line 3: 300 does not fit in a byte");
+    }
+}
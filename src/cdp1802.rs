@@ -0,0 +1,509 @@
+/// What the CDP1802 is connected to
+pub trait Bus {
+    fn read(&mut self, address: u16) -> u8;
+    fn write(&mut self, address: u16, value: u8);
+    /// `OUT n`, with the byte that is on the bus
+    fn output(&mut self, port: u8, value: u8);
+    /// `INP n`, the byte put on the bus
+    fn input(&mut self, port: u8) -> u8;
+    /// Whether the flag line EF1 to EF4 is asserted
+    fn flag(&self, line: u8) -> bool;
+}
+
+/// The RCA CDP1802, the processor of the COSMAC VIP.
+/// 16 registers of 16 bits, any of them can be the program counter (P) or the index (X).
+/// Instructions take 2 machine cycles (the long branches and skips 3), a machine cycle is 8 clocks.
+#[derive(Debug, Clone, Default)]
+pub struct Cdp1802 {
+    pub r: [u16; 16],
+    /// The register that is the program counter
+    pub p: u8,
+    /// The register that is the index
+    pub x: u8,
+    /// The accumulator
+    pub d: u8,
+    /// Carry, or no borrow for subtractions
+    pub df: bool,
+    /// X and P saved by an interrupt
+    pub t: u8,
+    /// Interrupts enabled
+    pub ie: bool,
+    /// The output flip flop, on the VIP it turns the tone on
+    pub q: bool,
+    /// Waiting for an interrupt or DMA after `IDL`
+    pub idle: bool,
+}
+
+impl Cdp1802 {
+    pub fn new() -> Cdp1802 {
+        let mut cpu = Cdp1802::default();
+        cpu.reset();
+        cpu
+    }
+
+    /// What the reset line does: X, P, Q and R0 are cleared and interrupts are enabled.
+    /// The other registers keep whatever they had.
+    pub fn reset(&mut self) {
+        self.x = 0;
+        self.p = 0;
+        self.q = false;
+        self.ie = true;
+        self.r[0] = 0;
+        self.idle = false;
+    }
+
+    /// Take an interrupt if they are enabled: X and P are saved in T,
+    /// R1 becomes the program counter and R2 the index
+    pub fn interrupt(&mut self) -> bool {
+        if !self.ie {
+            return false;
+        }
+        self.t = self.x << 4 | self.p;
+        self.x = 2;
+        self.p = 1;
+        self.ie = false;
+        self.idle = false;
+        true
+    }
+
+    /// A DMA out cycle: the byte R0 points at goes out, then R0 moves on
+    pub fn dma_out<B: Bus>(&mut self, bus: &mut B) -> u8 {
+        let value = bus.read(self.r[0]);
+        self.r[0] = self.r[0].wrapping_add(1);
+        self.idle = false;
+        value
+    }
+
+    /// Run one instruction, returns the machine cycles it took
+    pub fn step<B: Bus>(&mut self, bus: &mut B) -> u32 {
+        if self.idle {
+            return 1;
+        }
+        let opcode = self.fetch(bus);
+        let (i, n) = (opcode >> 4, opcode & 0xF);
+        let rn = n as usize;
+        let rx = self.x as usize;
+        match i {
+            // IDL
+            0x0 if n == 0 => self.idle = true,
+            // LDN
+            0x0 => self.d = bus.read(self.r[rn]),
+            // INC
+            0x1 => self.r[rn] = self.r[rn].wrapping_add(1),
+            // DEC
+            0x2 => self.r[rn] = self.r[rn].wrapping_sub(1),
+            // Short branches, only the low byte of the program counter changes
+            0x3 => {
+                let target = self.fetch(bus);
+                if self.condition(n, bus) {
+                    let pc = &mut self.r[self.p as usize];
+                    *pc = *pc & 0xFF00 | target as u16;
+                }
+            },
+            // LDA
+            0x4 => {
+                self.d = bus.read(self.r[rn]);
+                self.r[rn] = self.r[rn].wrapping_add(1);
+            },
+            // STR
+            0x5 => bus.write(self.r[rn], self.d),
+            // IRX
+            0x6 if n == 0 => self.r[rx] = self.r[rx].wrapping_add(1),
+            // OUT 1 to 7
+            0x6 if n < 8 => {
+                let value = bus.read(self.r[rx]);
+                self.r[rx] = self.r[rx].wrapping_add(1);
+                bus.output(n, value);
+            },
+            // Not used on the 1802
+            0x6 if n == 8 => (),
+            // INP 1 to 7
+            0x6 => {
+                let value = bus.input(n - 8);
+                bus.write(self.r[rx], value);
+                self.d = value;
+            },
+            0x7 => self.group_7(n, bus),
+            // GLO
+            0x8 => self.d = self.r[rn] as u8,
+            // GHI
+            0x9 => self.d = (self.r[rn] >> 8) as u8,
+            // PLO
+            0xA => self.r[rn] = self.r[rn] & 0xFF00 | self.d as u16,
+            // PHI
+            0xB => self.r[rn] = self.r[rn] & 0x00FF | (self.d as u16) << 8,
+            // Long branches and skips
+            0xC => return self.long_branch(n, bus),
+            // SEP
+            0xD => self.p = n,
+            // SEX
+            0xE => self.x = n,
+            0xF => self.group_f(n, bus),
+            _ => unreachable!(),
+        }
+        2
+    }
+
+    fn fetch<B: Bus>(&mut self, bus: &mut B) -> u8 {
+        let pc = &mut self.r[self.p as usize];
+        let value = bus.read(*pc);
+        *pc = pc.wrapping_add(1);
+        value
+    }
+
+    /// The condition of the branches, the upper half of the low nibble negates it
+    fn condition<B: Bus>(&self, n: u8, bus: &B) -> bool {
+        let condition = match n & 7 {
+            0 => true,
+            1 => self.q,
+            2 => self.d == 0,
+            3 => self.df,
+            line => bus.flag(line - 3),
+        };
+        condition != (n & 8 != 0)
+    }
+
+    fn long_branch<B: Bus>(&mut self, n: u8, bus: &mut B) -> u32 {
+        let pc = self.p as usize;
+        // Skips jump over the next 2 bytes, branches go to the address in them
+        let (skip, taken) = match n {
+            // NOP
+            0x4 => (true, false),
+            // LSNQ, LSNZ, LSNF
+            0x5 => (true, !self.q),
+            0x6 => (true, self.d != 0),
+            0x7 => (true, !self.df),
+            // LSKP
+            0x8 => (true, true),
+            // LSIE
+            0xC => (true, self.ie),
+            // LSQ, LSZ, LSDF
+            0xD => (true, self.q),
+            0xE => (true, self.d == 0),
+            0xF => (true, self.df),
+            // LBR, LBQ, LBZ, LBDF and the negated ones, without the flag lines
+            _ => (false, self.condition(n, bus)),
+        };
+        if skip {
+            if taken {
+                self.r[pc] = self.r[pc].wrapping_add(2);
+            }
+        } else if taken {
+            let high = self.fetch(bus);
+            let low = self.fetch(bus);
+            self.r[pc] = u16::from_be_bytes([high, low]);
+        } else {
+            self.r[pc] = self.r[pc].wrapping_add(2);
+        }
+        3
+    }
+
+    fn group_7<B: Bus>(&mut self, n: u8, bus: &mut B) {
+        let rx = self.x as usize;
+        match n {
+            // RET, DIS
+            0x0 | 0x1 => {
+                let value = bus.read(self.r[rx]);
+                self.r[rx] = self.r[rx].wrapping_add(1);
+                self.x = value >> 4;
+                self.p = value & 0xF;
+                self.ie = n == 0;
+            },
+            // LDXA
+            0x2 => {
+                self.d = bus.read(self.r[rx]);
+                self.r[rx] = self.r[rx].wrapping_add(1);
+            },
+            // STXD
+            0x3 => {
+                bus.write(self.r[rx], self.d);
+                self.r[rx] = self.r[rx].wrapping_sub(1);
+            },
+            // ADC, SDB, SMB
+            0x4 => self.add(bus.read(self.r[rx]), self.df),
+            0x5 => self.subtract(bus.read(self.r[rx]), self.d, self.df),
+            0x7 => self.subtract(self.d, bus.read(self.r[rx]), self.df),
+            // SHRC
+            0x6 => {
+                let carry = self.d & 1 != 0;
+                self.d = self.d >> 1 | (self.df as u8) << 7;
+                self.df = carry;
+            },
+            // SAV
+            0x8 => bus.write(self.r[rx], self.t),
+            // MARK
+            0x9 => {
+                self.t = self.x << 4 | self.p;
+                bus.write(self.r[2], self.t);
+                self.x = self.p;
+                self.r[2] = self.r[2].wrapping_sub(1);
+            },
+            // REQ, SEQ
+            0xA => self.q = false,
+            0xB => self.q = true,
+            // ADCI, SDBI, SMBI
+            0xC => {
+                let value = self.fetch(bus);
+                self.add(value, self.df);
+            },
+            0xD => {
+                let value = self.fetch(bus);
+                self.subtract(value, self.d, self.df);
+            },
+            0xF => {
+                let value = self.fetch(bus);
+                self.subtract(self.d, value, self.df);
+            },
+            // SHLC
+            0xE => {
+                let carry = self.d & 0x80 != 0;
+                self.d = self.d << 1 | self.df as u8;
+                self.df = carry;
+            },
+            _ => unreachable!(),
+        }
+    }
+
+    fn group_f<B: Bus>(&mut self, n: u8, bus: &mut B) {
+        // The lower half works on the byte X points at, the upper half on the next byte of the program
+        let value = match n {
+            // The shifts have no operand
+            0x6 | 0xE => 0,
+            0x0..=0x7 => bus.read(self.r[self.x as usize]),
+            _ => self.fetch(bus),
+        };
+        match n & 7 {
+            // LDX, LDI
+            0x0 => self.d = value,
+            // OR, ORI
+            0x1 => self.d |= value,
+            // AND, ANI
+            0x2 => self.d &= value,
+            // XOR, XRI
+            0x3 => self.d ^= value,
+            // ADD, ADI
+            0x4 => self.add(value, false),
+            // SD, SDI
+            0x5 => self.subtract(value, self.d, true),
+            // SHR, SHL
+            0x6 if n == 0x6 => {
+                self.df = self.d & 1 != 0;
+                self.d >>= 1;
+            },
+            0x6 => {
+                self.df = self.d & 0x80 != 0;
+                self.d <<= 1;
+            },
+            // SM, SMI
+            0x7 => self.subtract(self.d, value, true),
+            _ => unreachable!(),
+        }
+    }
+
+    fn add(&mut self, value: u8, carry: bool) {
+        let sum = self.d as u16 + value as u16 + carry as u16;
+        self.d = sum as u8;
+        self.df = sum > 0xFF;
+    }
+
+    /// `a - b`, DF is set when there was no borrow
+    fn subtract(&mut self, a: u8, b: u8, no_borrow: bool) {
+        let difference = a as i16 - b as i16 - !no_borrow as i16;
+        self.d = difference as u8;
+        self.df = difference >= 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 256 bytes of memory that repeat over the whole address space
+    struct Memory {
+        bytes: [u8; 0x100],
+        flags: [bool; 4],
+        outputs: Vec<(u8, u8)>,
+    }
+
+    impl Memory {
+        fn with_program(program: &[u8]) -> Memory {
+            let mut bytes = [0; 0x100];
+            bytes[..program.len()].copy_from_slice(program);
+            Memory { bytes, flags: [false; 4], outputs: Vec::new() }
+        }
+    }
+
+    impl Bus for Memory {
+        fn read(&mut self, address: u16) -> u8 {
+            self.bytes[address as u8 as usize]
+        }
+
+        fn write(&mut self, address: u16, value: u8) {
+            self.bytes[address as u8 as usize] = value;
+        }
+
+        fn output(&mut self, port: u8, value: u8) {
+            self.outputs.push((port, value));
+        }
+
+        fn input(&mut self, port: u8) -> u8 {
+            0x40 | port
+        }
+
+        fn flag(&self, line: u8) -> bool {
+            self.flags[line as usize - 1]
+        }
+    }
+
+    /// Run a single instruction from address 0 with D set,
+    /// returns the program counter after it and the cycles it took
+    fn branch(program: &[u8], d: u8) -> (u16, u32) {
+        let mut cpu = Cdp1802::new();
+        cpu.d = d;
+        let cycles = cpu.step(&mut Memory::with_program(program));
+        (cpu.r[0], cycles)
+    }
+
+    #[test]
+    fn short_branches() {
+        // BR, BZ and BNZ only replace the low byte
+        assert_eq!(branch(&[0x30, 0x42], 0), (0x42, 2));
+        assert_eq!(branch(&[0x32, 0x42], 0), (0x42, 2));
+        assert_eq!(branch(&[0x32, 0x42], 1), (0x02, 2));
+        assert_eq!(branch(&[0x3A, 0x42], 1), (0x42, 2));
+        // SKP is a branch that is never taken, it skips the byte after it
+        assert_eq!(branch(&[0x38, 0x42], 0), (0x02, 2));
+
+        let mut cpu = Cdp1802::new();
+        cpu.r[0] = 0x1200;
+        let mut memory = Memory::with_program(&[0x34, 0x42]);
+        memory.flags[0] = true;
+        // B1, with EF1 asserted, stays on the page
+        cpu.step(&mut memory);
+        assert_eq!(cpu.r[0], 0x1242);
+    }
+
+    #[test]
+    fn long_branches_and_skips() {
+        // LBR, LBZ taken and not
+        assert_eq!(branch(&[0xC0, 0x12, 0x34], 0), (0x1234, 3));
+        assert_eq!(branch(&[0xC2, 0x12, 0x34], 0), (0x1234, 3));
+        assert_eq!(branch(&[0xC2, 0x12, 0x34], 1), (0x0003, 3));
+        // NOP takes 3 cycles and does nothing
+        assert_eq!(branch(&[0xC4], 0), (0x0001, 3));
+        // LSKP always skips 2 bytes, LSZ and LSNZ only when D is (not) 0
+        assert_eq!(branch(&[0xC8], 0), (0x0003, 3));
+        assert_eq!(branch(&[0xCE], 0), (0x0003, 3));
+        assert_eq!(branch(&[0xCE], 1), (0x0001, 3));
+        assert_eq!(branch(&[0xC6], 1), (0x0003, 3));
+        // LSIE, interrupts are on after a reset
+        assert_eq!(branch(&[0xCC], 0), (0x0003, 3));
+    }
+
+    #[test]
+    fn subtract_with_borrow() {
+        // SDB: M(R(X)) - D - borrow, SMB: D - M(R(X)) - borrow, DF set means no borrow
+        let cases = [
+            // (opcode, D, M, DF before, D after, DF after)
+            (0x75, 3, 5, true, 2, true),
+            (0x75, 3, 5, false, 1, true),
+            (0x75, 5, 5, false, 0xFF, false),
+            (0x77, 5, 3, false, 1, true),
+            (0x77, 3, 3, false, 0xFF, false),
+            (0x77, 3, 5, true, 0xFE, false),
+        ];
+        for (opcode, d, m, df, result, borrowless) in cases {
+            let mut cpu = Cdp1802::new();
+            // X is R1, pointing at the operand
+            cpu.x = 1;
+            cpu.r[1] = 0x80;
+            cpu.d = d;
+            cpu.df = df;
+            let mut memory = Memory::with_program(&[opcode]);
+            memory.bytes[0x80] = m;
+            cpu.step(&mut memory);
+            assert_eq!((cpu.d, cpu.df), (result, borrowless), "{:02X} with D {} M {} DF {}", opcode, d, m, df);
+        }
+
+        // SMBI, the operand follows
+        let mut cpu = Cdp1802::new();
+        cpu.d = 0x10;
+        cpu.step(&mut Memory::with_program(&[0x7F, 0x01]));
+        assert_eq!((cpu.d, cpu.df, cpu.r[0]), (0x0E, true, 2));
+    }
+
+    #[test]
+    fn mark_return_disable() {
+        let mut cpu = Cdp1802::new();
+        let mut memory = Memory::with_program(&[0x79, 0x70, 0x71]);
+        cpu.x = 3;
+        cpu.r[2] = 0x80;
+
+        // MARK saves X and P in T and at R2, which moves down, and X becomes P
+        cpu.step(&mut memory);
+        assert_eq!((cpu.t, memory.bytes[0x80], cpu.x, cpu.r[2]), (0x30, 0x30, 0, 0x7F));
+
+        // RET takes X and P from where X points and turns interrupts on
+        cpu.ie = false;
+        cpu.x = 4;
+        cpu.r[4] = 0x90;
+        memory.bytes[0x90] = 0x50;
+        memory.bytes[0x91] = 0x00;
+        cpu.step(&mut memory);
+        assert_eq!((cpu.x, cpu.p, cpu.r[4], cpu.ie), (5, 0, 0x91, true));
+
+        // DIS does the same and turns them off
+        cpu.r[5] = 0x91;
+        cpu.step(&mut memory);
+        assert_eq!((cpu.x, cpu.p, cpu.r[5], cpu.ie), (0, 0, 0x92, false));
+    }
+
+    #[test]
+    fn interrupts() {
+        let mut cpu = Cdp1802::new();
+        let mut memory = Memory::with_program(&[0x00]);
+        cpu.x = 5;
+        cpu.p = 3;
+        cpu.r[3] = 0;
+
+        // IDL waits, a cycle at a time
+        cpu.step(&mut memory);
+        assert!(cpu.idle);
+        assert_eq!(cpu.step(&mut memory), 1);
+
+        // An interrupt saves X and P, R1 runs with R2 as the index
+        assert!(cpu.interrupt());
+        assert_eq!((cpu.t, cpu.x, cpu.p, cpu.ie, cpu.idle), (0x53, 2, 1, false, false));
+        // and they stay off until the program turns them on again
+        assert!(!cpu.interrupt());
+    }
+
+    #[test]
+    fn dma() {
+        let mut cpu = Cdp1802::new();
+        let mut memory = Memory::with_program(&[0x00]);
+        cpu.step(&mut memory);
+        cpu.r[0] = 0x40;
+        memory.bytes[0x40] = 0xAB;
+
+        // DMA out sends the byte at R0 and ends the wait
+        assert_eq!(cpu.dma_out(&mut memory), 0xAB);
+        assert_eq!(cpu.r[0], 0x41);
+        assert!(!cpu.idle);
+    }
+
+    #[test]
+    fn input_output() {
+        let mut cpu = Cdp1802::new();
+        // X is R0, the byte after OUT is what goes out
+        let mut memory = Memory::with_program(&[0x62, 0x99, 0x6B]);
+        assert_eq!(cpu.step(&mut memory), 2);
+        assert_eq!(memory.outputs, [(2, 0x99)]);
+
+        // INP 3 puts the byte into D and where X points
+        cpu.x = 1;
+        cpu.r[1] = 0x80;
+        cpu.step(&mut memory);
+        assert_eq!((cpu.d, memory.bytes[0x80]), (0x43, 0x43));
+    }
+}
@@ -0,0 +1,351 @@
+use std::path::PathBuf;
+
+use clap::{Args, Parser, Subcommand};
+
+use crate::config::{self, parse_address, Config};
+use crate::keymap::Keymap;
+use crate::machine::Backend;
+use crate::palette::{self, Theme};
+use crate::platform::{Platform, Quirk};
+use crate::random::Generator;
+use crate::scheduler::Timing;
+use crate::stack::StackOverflow;
+use crate::tui::Glyphs;
+use crate::{ScaleMode, UnknownOpcodes};
+
+/// wrack - a rusted chip-8 implementation
+///
+/// Settings are read from $XDG_CONFIG_HOME/wrack/config.toml,
+/// the options on the command line go on top of them.
+#[derive(Debug, Parser)]
+#[command(name = "wrack", version)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Read the settings from this file instead of the default config file
+    #[arg(long, global = true, value_name = "FILE")]
+    pub config: Option<PathBuf>,
+
+    /// Without a subcommand the ROM is run
+    #[command(flatten)]
+    pub run: RunArgs,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Run a ROM in a window or the terminal
+    Run(RunArgs),
+    /// Print the instructions of a ROM
+    Disasm(DisasmArgs),
+    /// Show what is known about a ROM
+    Info(InfoArgs),
+    /// Run a ROM without a window as fast as possible
+    Bench(BenchArgs),
+    /// Print the settings that are used, after putting all config layers together
+    Config(ConfigArgs),
+}
+
+/// How the chip is set up
+#[derive(Debug, Args)]
+pub struct MachineArgs {
+    /// The interpreter the ROM was written for, decides the default quirks [default: modern]
+    #[arg(long, value_enum)]
+    pub platform: Option<Platform>,
+
+    /// chip8 runs the ROM in wrack, vip runs it on an emulated COSMAC VIP with its original
+    /// interpreter (which needs --vip-interpreter) [default: chip8]
+    #[arg(long, value_enum)]
+    pub backend: Option<Backend>,
+
+    /// Turn a quirk on, on top of the ones of the platform
+    #[arg(long = "quirk", value_enum, value_name = "QUIRK")]
+    pub quirks_on: Vec<Quirk>,
+
+    /// Turn a quirk off, which the platform would have on
+    #[arg(long = "no-quirk", value_enum, value_name = "QUIRK")]
+    pub quirks_off: Vec<Quirk>,
+
+    /// Instructions executed per frame, there are 60 frames a second [default: 11]
+    #[arg(long)]
+    pub ipf: Option<u32>,
+
+    /// ipf runs the same number of instructions every frame, vip takes as long
+    /// for every instruction as the VIP did [default: ipf]
+    #[arg(long, value_enum)]
+    pub timing: Option<Timing>,
+
+    /// Seed for the random numbers, to get the same game every time
+    #[arg(long)]
+    pub seed: Option<u64>,
+
+    /// Where the random numbers come from: standard, or vip for the routine of the VIP
+    /// (which needs --vip-interpreter) [default: standard]
+    #[arg(long, value_enum)]
+    pub random: Option<Generator>,
+
+    /// The CHIP-8 interpreter of the COSMAC VIP, the first 512 bytes of its memory
+    #[arg(long, value_name = "FILE")]
+    pub vip_interpreter: Option<PathBuf>,
+
+    /// The monitor ROM of the VIP (512 bytes) for the VIP backend,
+    /// without it the VIP starts right at the interpreter
+    #[arg(long, value_name = "FILE")]
+    pub vip_monitor: Option<PathBuf>,
+
+    /// What to do about unknown instructions and calls of machine code (0nnn):
+    /// ignore them, log them, pause or halt [default: log]
+    #[arg(long, value_enum, value_name = "POLICY")]
+    pub unknown_opcodes: Option<UnknownOpcodes>,
+
+    /// The address the ROM is loaded at [default: the one of the platform, 0x200 for most]
+    #[arg(long, value_parser = parse_address)]
+    pub load_address: Option<u16>,
+
+    /// The digits Fx29 and Fx30 point at: vip, eti660, dream6800, fish, octo, schip or a font file
+    /// [default: the one of the platform]
+    #[arg(long, value_name = "FONT")]
+    pub font: Option<String>,
+
+    /// Where the font is put in memory [default: the one of the platform]
+    #[arg(long, value_parser = parse_address)]
+    pub font_address: Option<u16>,
+
+    /// Bytes of memory, 65536 for XO-CHIP, up to 16M for MEGA-CHIP [default: the one of the platform]
+    #[arg(long, value_parser = clap::value_parser!(u32).range(0x200..=0x1000000))]
+    pub memory_size: Option<u32>,
+
+    /// Keep a range of memory like 0xEA0-0xFFF for the interpreter, no ROM can be loaded there.
+    /// Replaces the ranges of the platform
+    #[arg(long = "reserved", value_parser = parse_range, value_name = "RANGE")]
+    pub reserved: Vec<String>,
+
+    /// How many subroutine calls fit on the stack [default: 16, 12 on the VIP]
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    pub stack_depth: Option<u32>,
+
+    /// What happens when the stack is full: fault stops the chip, wrap overwrites the oldest call,
+    /// grow makes room [default: fault]
+    #[arg(long, value_enum)]
+    pub stack_overflow: Option<StackOverflow>,
+
+    /// A ROM database (the programs.json of the chip-8-database, or its directory)
+    /// that goes on top of the bundled one
+    #[arg(long, value_name = "PATH")]
+    pub database: Option<PathBuf>,
+
+    /// Octo options (JSON) for the ROM, instead of the .json with the same name next to it
+    #[arg(long, value_name = "FILE")]
+    pub octo_options: Option<PathBuf>,
+}
+
+impl MachineArgs {
+    /// The options as the top layer of the config
+    pub fn to_config(&self) -> Config {
+        let mut config = Config {
+            platform: self.platform,
+            backend: self.backend,
+            ipf: self.ipf,
+            timing: self.timing,
+            load_address: self.load_address,
+            font: self.font.clone(),
+            font_address: self.font_address,
+            memory_size: self.memory_size,
+            reserved: (!self.reserved.is_empty()).then(|| self.reserved.clone()),
+            stack_depth: self.stack_depth,
+            stack_overflow: self.stack_overflow,
+            random: self.random,
+            vip_interpreter: self.vip_interpreter.clone(),
+            vip_monitor: self.vip_monitor.clone(),
+            unknown_opcodes: self.unknown_opcodes,
+            database: self.database.clone(),
+            ..Config::default()
+        };
+        for quirk in &self.quirks_on {
+            config.quirks.set(*quirk, true);
+        }
+        for quirk in &self.quirks_off {
+            config.quirks.set(*quirk, false);
+        }
+        config
+    }
+}
+
+/// How the ROM is shown
+#[derive(Debug, Args)]
+pub struct FrontendArgs {
+    /// Initial size of the window, as a multiple of the display [default: 15]
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    pub scale: Option<u32>,
+
+    /// How the display is scaled into the window [default: integer]
+    #[arg(long, value_enum)]
+    pub scale_mode: Option<ScaleMode>,
+
+    /// Start in fullscreen
+    #[arg(long)]
+    pub fullscreen: bool,
+
+    /// Turn the picture clockwise by 0, 90, 180 or 270 degrees, for games made for a screen on its side [default: 0]
+    #[arg(long, value_name = "DEGREES")]
+    pub rotation: Option<u16>,
+
+    /// Color theme: classic, amber, green, lcd or octo [default: classic]
+    #[arg(long, value_parser = parse_theme)]
+    pub palette: Option<String>,
+
+    /// Foreground color, as RRGGBB
+    #[arg(long, value_parser = parse_color)]
+    pub fg: Option<String>,
+
+    /// Background color, as RRGGBB
+    #[arg(long, value_parser = parse_color)]
+    pub bg: Option<String>,
+
+    /// Let erased pixels fade out, DECAY is how much of the glow is kept each frame (0 to 1)
+    #[arg(long, value_name = "DECAY")]
+    pub phosphor: Option<f64>,
+
+    /// Keyboard layout: linear, cosmac or 16 keys for the keypad keys 0 to F separated by commas [default: linear]
+    #[arg(long, value_parser = parse_keymap)]
+    pub keymap: Option<String>,
+}
+
+impl FrontendArgs {
+    /// The options as the top layer of the config
+    pub fn to_config(&self) -> Config {
+        Config {
+            scale: self.scale,
+            scale_mode: self.scale_mode,
+            fullscreen: self.fullscreen.then_some(true),
+            rotation: self.rotation,
+            palette: self.palette.clone(),
+            fg: self.fg.clone(),
+            bg: self.bg.clone(),
+            phosphor: self.phosphor,
+            keymap: self.keymap.clone(),
+            ..Config::default()
+        }
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct RunArgs {
+    /// The ROM to run: a file, an Octo cartridge, archive.zip#entry or - for standard input
+    // Only optional for the derive, so a subcommand can be given instead of a ROM
+    #[arg(required = true)]
+    pub rom: Option<PathBuf>,
+
+    #[command(flatten)]
+    pub machine: MachineArgs,
+
+    #[command(flatten)]
+    pub frontend: FrontendArgs,
+
+    /// Record from the start into a .gif or .y4m file
+    #[arg(long, value_name = "FILE")]
+    pub record: Option<PathBuf>,
+
+    /// Load the ROM again and start over whenever it changes on disk
+    #[arg(long)]
+    pub watch: bool,
+
+    /// Run in the terminal instead of a window, with half blocks or braille dots [default: half]
+    #[arg(long, value_enum, value_name = "GLYPHS", num_args = 0..=1, default_missing_value = "half")]
+    pub terminal: Option<Glyphs>,
+}
+
+impl RunArgs {
+    /// The options as the top layer of the config
+    pub fn to_config(&self) -> Config {
+        self.machine.to_config().merged(&self.frontend.to_config())
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct DisasmArgs {
+    /// The ROM to disassemble
+    pub rom: PathBuf,
+
+    /// The address the ROM is loaded at [default: the one of the platform]
+    #[arg(long, value_parser = parse_address)]
+    pub load_address: Option<u16>,
+}
+
+#[derive(Debug, Args)]
+pub struct InfoArgs {
+    /// The ROM to look at
+    pub rom: PathBuf,
+
+    /// The address the ROM is loaded at [default: the one of the platform]
+    #[arg(long, value_parser = parse_address)]
+    pub load_address: Option<u16>,
+
+    /// A ROM database that goes on top of the bundled one
+    #[arg(long, value_name = "PATH")]
+    pub database: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+pub struct BenchArgs {
+    /// The ROM to run
+    pub rom: PathBuf,
+
+    #[command(flatten)]
+    pub machine: MachineArgs,
+
+    /// How many frames to run
+    #[arg(long, default_value_t = 6000)]
+    pub frames: u32,
+}
+
+#[derive(Debug, Args)]
+pub struct ConfigArgs {
+    /// Include the settings for this ROM
+    pub rom: Option<PathBuf>,
+
+    /// Print the settings as Octo options (JSON)
+    #[arg(long)]
+    pub octo: bool,
+
+    #[command(flatten)]
+    pub machine: MachineArgs,
+
+    #[command(flatten)]
+    pub frontend: FrontendArgs,
+}
+
+impl ConfigArgs {
+    /// The options as the top layer of the config
+    pub fn to_config(&self) -> Config {
+        self.machine.to_config().merged(&self.frontend.to_config())
+    }
+}
+
+// The values of the frontend are checked right away, but kept as text for the config
+
+fn parse_theme(text: &str) -> Result<String, String> {
+    match Theme::from_name(text) {
+        Some(theme) => Ok(theme.name().to_string()),
+        None => {
+            let names: Vec<&str> = Theme::ALL.iter().map(|theme| theme.name()).collect();
+            Err(format!("unknown palette, use one of {}", names.join(", ")))
+        },
+    }
+}
+
+fn parse_color(text: &str) -> Result<String, String> {
+    match palette::parse_color(text) {
+        Some(_) => Ok(text.to_string()),
+        None => Err(format!("{} is not a color like FFCC00", text)),
+    }
+}
+
+fn parse_range(text: &str) -> Result<String, String> {
+    config::parse_range(text).map(|_| text.to_string())
+}
+
+fn parse_keymap(text: &str) -> Result<String, String> {
+    Keymap::parse(text).map(|_| text.to_string())
+}
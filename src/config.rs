@@ -0,0 +1,559 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::flags::Flags;
+use crate::font::Font;
+use crate::keymap::Keymap;
+use crate::machine::{Backend, Machine};
+use crate::palette::{self, Palette, Theme};
+use crate::platform::{Platform, Quirk, Quirks};
+use crate::random::{Generator, Standard, Vip};
+use crate::recorder::{self, Audio};
+use crate::rom;
+use crate::scheduler::{Timing, DEFAULT_IPF};
+use crate::stack::{Stack, StackOverflow};
+use crate::vip::{CosmacVip, MONITOR_SIZE};
+use crate::{Cpu, Display, Rotation, ScaleMode, UnknownOpcodes};
+
+/// The settings, as they are written in the config file.
+/// Everything is optional, so the layers can be put on top of each other:
+/// built in defaults, what is known about the ROM (from the database and the ROM itself),
+/// the config file, the section for the ROM in it and the command line.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Config {
+    pub platform: Option<Platform>,
+    /// wrack itself or a COSMAC VIP running its interpreter
+    pub backend: Option<Backend>,
+    pub ipf: Option<u32>,
+    /// A fixed number of instructions per frame or the time they took on the VIP
+    pub timing: Option<Timing>,
+    pub load_address: Option<u16>,
+    /// A built in font or a font file, the platform decides when there is none
+    pub font: Option<String>,
+    pub font_address: Option<u16>,
+    /// Bytes of memory, up to 64K (16M for MEGA-CHIP)
+    pub memory_size: Option<u32>,
+    /// Ranges like `0xEA0-0xFFF` that are kept for the interpreter
+    pub reserved: Option<Vec<String>>,
+    /// How many subroutine calls fit on the stack
+    pub stack_depth: Option<u32>,
+    pub stack_overflow: Option<StackOverflow>,
+    /// Where the random numbers come from
+    pub random: Option<Generator>,
+    /// The CHIP-8 interpreter of the COSMAC VIP (its first 512 bytes of memory),
+    /// for the random numbers of the VIP
+    pub vip_interpreter: Option<PathBuf>,
+    /// The monitor ROM of the VIP (512 bytes), for the VIP backend
+    pub vip_monitor: Option<PathBuf>,
+    /// What happens on instructions that aren't known
+    pub unknown_opcodes: Option<UnknownOpcodes>,
+    pub scale: Option<u32>,
+    pub scale_mode: Option<ScaleMode>,
+    pub fullscreen: Option<bool>,
+    /// Degrees the picture is turned clockwise: 0, 90, 180 or 270
+    pub rotation: Option<u16>,
+    pub palette: Option<String>,
+    pub fg: Option<String>,
+    pub bg: Option<String>,
+    /// All colors of the palette, background first
+    pub colors: Option<Vec<String>>,
+    pub phosphor: Option<f64>,
+    pub keymap: Option<String>,
+    /// A ROM database to use on top of the bundled one
+    pub database: Option<PathBuf>,
+    #[serde(skip_serializing_if = "QuirksConfig::is_empty")]
+    pub quirks: QuirksConfig,
+    #[serde(skip_serializing_if = "AudioConfig::is_empty")]
+    pub audio: AudioConfig,
+    /// Settings for single ROMs, keyed by the SHA-1 of the ROM
+    #[serde(rename = "rom", skip_serializing_if = "BTreeMap::is_empty")]
+    pub roms: BTreeMap<String, Config>,
+}
+
+/// Quirks that differ from the ones of the platform
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct QuirksConfig {
+    pub shift: Option<bool>,
+    pub load_store: Option<bool>,
+    pub vf_reset: Option<bool>,
+    pub jump: Option<bool>,
+    pub clip: Option<bool>,
+    pub display_wait: Option<bool>,
+    pub vf_order: Option<bool>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct AudioConfig {
+    /// Frequency of the beep in Hz
+    pub tone: Option<u32>,
+    /// Between 0 and 1
+    pub volume: Option<f64>,
+}
+
+impl QuirksConfig {
+    pub fn set(&mut self, quirk: Quirk, on: bool) {
+        let field = match quirk {
+            Quirk::Shift => &mut self.shift,
+            Quirk::LoadStore => &mut self.load_store,
+            Quirk::VfReset => &mut self.vf_reset,
+            Quirk::Jump => &mut self.jump,
+            Quirk::Clip => &mut self.clip,
+            Quirk::DisplayWait => &mut self.display_wait,
+            Quirk::VfOrder => &mut self.vf_order,
+        };
+        *field = Some(on);
+    }
+
+    fn is_empty(&self) -> bool {
+        *self == QuirksConfig::default()
+    }
+
+    pub fn merged(&self, over: &QuirksConfig) -> QuirksConfig {
+        QuirksConfig {
+            shift: over.shift.or(self.shift),
+            load_store: over.load_store.or(self.load_store),
+            vf_reset: over.vf_reset.or(self.vf_reset),
+            jump: over.jump.or(self.jump),
+            clip: over.clip.or(self.clip),
+            display_wait: over.display_wait.or(self.display_wait),
+            vf_order: over.vf_order.or(self.vf_order),
+        }
+    }
+
+    /// Put the quirks on top of the ones of a platform
+    fn apply(&self, mut quirks: Quirks) -> Quirks {
+        quirks.shift = self.shift.unwrap_or(quirks.shift);
+        quirks.load_store = self.load_store.unwrap_or(quirks.load_store);
+        quirks.vf_reset = self.vf_reset.unwrap_or(quirks.vf_reset);
+        quirks.jump = self.jump.unwrap_or(quirks.jump);
+        quirks.clip = self.clip.unwrap_or(quirks.clip);
+        quirks.display_wait = self.display_wait.unwrap_or(quirks.display_wait);
+        quirks.vf_order = self.vf_order.unwrap_or(quirks.vf_order);
+        quirks
+    }
+
+    /// All quirks written out
+    fn from_quirks(quirks: Quirks) -> QuirksConfig {
+        QuirksConfig {
+            shift: Some(quirks.shift),
+            load_store: Some(quirks.load_store),
+            vf_reset: Some(quirks.vf_reset),
+            jump: Some(quirks.jump),
+            clip: Some(quirks.clip),
+            display_wait: Some(quirks.display_wait),
+            vf_order: Some(quirks.vf_order),
+        }
+    }
+}
+
+impl AudioConfig {
+    fn is_empty(&self) -> bool {
+        *self == AudioConfig::default()
+    }
+
+    fn merged(&self, over: &AudioConfig) -> AudioConfig {
+        AudioConfig {
+            tone: over.tone.or(self.tone),
+            volume: over.volume.or(self.volume),
+        }
+    }
+}
+
+impl Config {
+    /// `$XDG_CONFIG_HOME/wrack/config.toml`, or wherever configs go on the system
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("wrack").join("config.toml"))
+    }
+
+    /// Read a config file
+    pub fn load(path: &Path) -> Result<Config, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| format!("could not read {}: {}", path.display(), e))?;
+        toml::from_str(&text).map_err(|e| format!("could not parse {}: {}", path.display(), e))
+    }
+
+    /// Read the given config file or the default one.
+    /// Not having a default config file is the same as an empty one.
+    /// Returns the path of the file next to the config.
+    pub fn load_file(path: Option<&Path>) -> Result<(Config, Option<PathBuf>), String> {
+        if let Some(path) = path {
+            return Ok((Config::load(path)?, Some(path.to_path_buf())));
+        }
+        match Config::default_path() {
+            Some(path) if path.exists() => Ok((Config::load(&path)?, Some(path))),
+            _ => Ok((Config::default(), None)),
+        }
+    }
+
+    /// Put all layers on top of each other: the built in defaults, what is known
+    /// about the ROM, the config file, the section for the ROM in it and the command line
+    pub fn layered(file: &Config, known: Option<&Config>, hash: Option<&str>, cli: &Config) -> Config {
+        let mut config = Config::defaults();
+        if let Some(known) = known {
+            config = config.merged(known);
+        }
+        config = config.merged(file);
+        if let Some(rom) = hash.and_then(|hash| file.for_rom(hash)) {
+            config = config.merged(rom);
+        }
+        config.merged(cli)
+    }
+
+    /// The built in defaults with every setting filled in
+    pub fn defaults() -> Config {
+        let platform = Platform::default();
+        let layout = platform.layout();
+        Config {
+            platform: Some(platform),
+            backend: Some(Backend::default()),
+            ipf: Some(DEFAULT_IPF),
+            timing: Some(Timing::default()),
+            load_address: Some(layout.load_address),
+            font: Some(platform.font().name().to_string()),
+            font_address: Some(layout.font_address),
+            memory_size: Some(layout.memory_size as u32),
+            reserved: Some(format_reserved(&layout.reserved)),
+            stack_depth: Some(platform.stack_depth() as u32),
+            stack_overflow: Some(StackOverflow::default()),
+            random: Some(Generator::default()),
+            vip_interpreter: None,
+            vip_monitor: None,
+            unknown_opcodes: Some(UnknownOpcodes::default()),
+            scale: Some(15),
+            scale_mode: Some(ScaleMode::Integer),
+            fullscreen: Some(false),
+            rotation: Some(0),
+            palette: Some(Theme::Classic.name().to_string()),
+            fg: None,
+            bg: None,
+            colors: None,
+            phosphor: None,
+            keymap: Some("linear".to_string()),
+            database: None,
+            quirks: QuirksConfig::from_quirks(platform.quirks()),
+            audio: AudioConfig {
+                tone: Some(recorder::DEFAULT_TONE),
+                volume: Some(recorder::DEFAULT_VOLUME as f64),
+            },
+            roms: BTreeMap::new(),
+        }
+    }
+
+    /// The section for a ROM, if there is one
+    pub fn for_rom(&self, hash: &str) -> Option<&Config> {
+        self.roms.get(hash)
+    }
+
+    /// Put `over` on top of this config, the settings in `over` win
+    pub fn merged(&self, over: &Config) -> Config {
+        // The quirks of a platform replace the ones from below,
+        // quirks given together with the platform still go on top
+        let quirks = match over.platform {
+            Some(platform) => QuirksConfig::from_quirks(platform.quirks()).merged(&over.quirks),
+            None => self.quirks.merged(&over.quirks),
+        };
+        // And its font, memory layout and stack
+        let layout = over.platform.map(|platform| platform.layout());
+        let font = match over.platform {
+            Some(platform) => over.font.clone().or_else(|| Some(platform.font().name().to_string())),
+            None => over.font.clone().or_else(|| self.font.clone()),
+        };
+        // Same for the colors of a palette, which only stay when no other palette is picked
+        let colors = match over.palette {
+            Some(_) => over.colors.clone(),
+            None => over.colors.clone().or_else(|| self.colors.clone()),
+        };
+        Config {
+            platform: over.platform.or(self.platform),
+            backend: over.backend.or(self.backend),
+            ipf: over.ipf.or(self.ipf),
+            timing: over.timing.or(self.timing),
+            load_address: over.load_address.or(layout.as_ref().map(|layout| layout.load_address)).or(self.load_address),
+            font,
+            font_address: over.font_address.or(layout.as_ref().map(|layout| layout.font_address)).or(self.font_address),
+            memory_size: over.memory_size.or(layout.as_ref().map(|layout| layout.memory_size as u32)).or(self.memory_size),
+            reserved: over
+                .reserved
+                .clone()
+                .or_else(|| layout.as_ref().map(|layout| format_reserved(&layout.reserved)))
+                .or_else(|| self.reserved.clone()),
+            stack_depth: over.stack_depth.or(over.platform.map(|platform| platform.stack_depth() as u32)).or(self.stack_depth),
+            stack_overflow: over.stack_overflow.or(self.stack_overflow),
+            random: over.random.or(self.random),
+            vip_interpreter: over.vip_interpreter.clone().or_else(|| self.vip_interpreter.clone()),
+            vip_monitor: over.vip_monitor.clone().or_else(|| self.vip_monitor.clone()),
+            unknown_opcodes: over.unknown_opcodes.or(self.unknown_opcodes),
+            scale: over.scale.or(self.scale),
+            scale_mode: over.scale_mode.or(self.scale_mode),
+            fullscreen: over.fullscreen.or(self.fullscreen),
+            rotation: over.rotation.or(self.rotation),
+            palette: over.palette.clone().or_else(|| self.palette.clone()),
+            fg: over.fg.clone().or_else(|| self.fg.clone()),
+            bg: over.bg.clone().or_else(|| self.bg.clone()),
+            colors,
+            phosphor: over.phosphor.or(self.phosphor),
+            keymap: over.keymap.clone().or_else(|| self.keymap.clone()),
+            database: over.database.clone().or_else(|| self.database.clone()),
+            quirks,
+            audio: self.audio.merged(&over.audio),
+            // Only the top level has sections for the ROMs
+            roms: BTreeMap::new(),
+        }
+    }
+
+    /// Turn the config into settings that can be used, checking the values on the way
+    pub fn resolve(&self) -> Result<Settings, String> {
+        let platform = self.platform.unwrap_or_default();
+        let theme = match &self.palette {
+            Some(name) => Theme::from_name(name).ok_or_else(|| format!("unknown palette {}", name))?,
+            None => Theme::Classic,
+        };
+        let mut palette = theme.palette();
+        if let Some(colors) = &self.colors {
+            if colors.len() > palette.colors.len() {
+                return Err(format!("a palette has {} colors, not {}", palette.colors.len(), colors.len()));
+            }
+            for (slot, color) in palette.colors.iter_mut().zip(colors) {
+                *slot = parse_color(color)?;
+            }
+        }
+        if let Some(color) = &self.fg {
+            palette = palette.with_foreground(parse_color(color)?);
+        }
+        if let Some(color) = &self.bg {
+            palette = palette.with_background(parse_color(color)?);
+        }
+        let keymap = match &self.keymap {
+            Some(keymap) => Keymap::parse(keymap)?,
+            None => Keymap::default(),
+        };
+        let font = match &self.font {
+            Some(font) => Font::load(font)?,
+            None => platform.font().font(),
+        };
+        let default = platform.layout();
+        let memory_size = self.memory_size.map_or(default.memory_size, |size| size as usize);
+        let (max_size, max_name) = match platform {
+            Platform::Megachip => (0x1000000, "16M"),
+            _ => (0x10000, "64K"),
+        };
+        if !(0x200..=max_size).contains(&memory_size) {
+            return Err(format!("the memory needs to be between 512 bytes and {}, not {} bytes", max_name, memory_size));
+        }
+        let load_address = self.load_address.unwrap_or(default.load_address);
+        if load_address as usize >= memory_size {
+            return Err(format!("the load address {:#05X} is past the end of the memory", load_address));
+        }
+        let font_address = self.font_address.unwrap_or(default.font_address);
+        if font_address as usize + font.len() > memory_size {
+            return Err(format!("the font doesn't fit into memory at {:#05X}", font_address));
+        }
+        let reserved = match &self.reserved {
+            Some(ranges) => ranges.iter().map(|range| parse_range(range)).collect::<Result<_, _>>()?,
+            None => default.reserved,
+        };
+        let stack_depth = self.stack_depth.map_or(platform.stack_depth(), |depth| depth as usize);
+        if stack_depth == 0 {
+            return Err("the stack needs room for at least one call".to_string());
+        }
+        let vip_interpreter = self.vip_interpreter.as_deref().map(read_file).transpose()?;
+        let vip_monitor = self.vip_monitor.as_deref().map(read_file).transpose()?;
+        let backend = self.backend.unwrap_or_default();
+        if backend == Backend::Vip {
+            if vip_interpreter.is_none() {
+                return Err("the VIP backend needs the CHIP-8 interpreter of the VIP, set vip-interpreter".to_string());
+            }
+            if platform == Platform::Megachip {
+                return Err("the VIP can't run MEGA-CHIP".to_string());
+            }
+            if !memory_size.is_power_of_two() || memory_size > 0x8000 {
+                return Err(format!("the VIP has a power of two bytes of RAM up to 32K, not {} bytes", memory_size));
+            }
+            if let Some(monitor) = &vip_monitor {
+                if monitor.len() != MONITOR_SIZE {
+                    return Err(format!("the monitor of the VIP has {} bytes, not {}", MONITOR_SIZE, monitor.len()));
+                }
+            }
+        }
+        let random = self.random.unwrap_or_default();
+        match (random, &vip_interpreter) {
+            (Generator::Vip, None) => {
+                return Err("the random numbers of the VIP need its interpreter, set vip-interpreter".to_string())
+            },
+            (Generator::Vip, Some(interpreter)) => {
+                Vip::new(interpreter)?;
+            },
+            _ => (),
+        }
+        if let Some(decay) = self.phosphor {
+            if !(0.0..=1.0).contains(&decay) {
+                return Err(format!("the phosphor decay needs to be between 0 and 1, not {}", decay));
+            }
+        }
+
+        let rotation = match self.rotation {
+            Some(degrees) => Rotation::from_degrees(degrees)
+                .ok_or_else(|| format!("the rotation needs to be 0, 90, 180 or 270, not {}", degrees))?,
+            None => Rotation::default(),
+        };
+
+        Ok(Settings {
+            platform,
+            backend,
+            quirks: self.quirks.apply(platform.quirks()),
+            ipf: self.ipf.unwrap_or(DEFAULT_IPF),
+            timing: self.timing.unwrap_or_default(),
+            load_address,
+            font,
+            font_address,
+            memory_size,
+            reserved,
+            stack_depth,
+            stack_overflow: self.stack_overflow.unwrap_or_default(),
+            random,
+            vip_interpreter,
+            vip_monitor,
+            unknown_opcodes: self.unknown_opcodes.unwrap_or_default(),
+            scale: self.scale.unwrap_or(15).max(1),
+            scale_mode: self.scale_mode.unwrap_or(ScaleMode::Integer),
+            fullscreen: self.fullscreen.unwrap_or(false),
+            rotation,
+            theme,
+            palette,
+            phosphor: self.phosphor.map(|decay| decay as f32),
+            keymap,
+            audio: Audio {
+                tone: self.audio.tone.unwrap_or(recorder::DEFAULT_TONE),
+                volume: self.audio.volume.map_or(recorder::DEFAULT_VOLUME, |v| v as f32).clamp(0.0, 1.0),
+            },
+        })
+    }
+
+    /// The config as it would be written in the file
+    pub fn to_toml(&self) -> Result<String, String> {
+        toml::to_string(self).map_err(|e| e.to_string())
+    }
+}
+
+/// An address, either decimal or hexadecimal with a leading 0x
+pub fn parse_address(text: &str) -> Result<u16, String> {
+    let parsed = match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => u16::from_str_radix(hex, 16),
+        None => text.parse::<u16>(),
+    };
+    parsed.map_err(|e| format!("{} is not an address: {}", text, e))
+}
+
+/// A range of addresses like `0xEA0-0xFFF`, both ends included
+pub fn parse_range(text: &str) -> Result<(u16, u16), String> {
+    let (first, last) = text
+        .split_once('-')
+        .ok_or_else(|| format!("{} is not a range of addresses like 0xEA0-0xFFF", text))?;
+    let (first, last) = (parse_address(first.trim())?, parse_address(last.trim())?);
+    if first > last {
+        return Err(format!("the range {} ends before it starts", text));
+    }
+    Ok((first, last))
+}
+
+fn format_reserved(ranges: &[(u16, u16)]) -> Vec<String> {
+    ranges.iter().map(|(first, last)| format!("{:#05X}-{:#05X}", first, last)).collect()
+}
+
+fn read_file(path: &Path) -> Result<Vec<u8>, String> {
+    fs::read(path).map_err(|e| format!("could not read {}: {}", path.display(), e))
+}
+
+fn parse_color(text: &str) -> Result<palette::Color, String> {
+    palette::parse_color(text).ok_or_else(|| format!("{} is not a color like FFCC00", text))
+}
+
+/// Everything the config decides, ready to use
+#[derive(Debug, Clone)]
+pub struct Settings {
+    pub platform: Platform,
+    pub backend: Backend,
+    pub quirks: Quirks,
+    pub ipf: u32,
+    pub timing: Timing,
+    pub load_address: u16,
+    pub font: Font,
+    pub font_address: u16,
+    pub memory_size: usize,
+    pub reserved: Vec<(u16, u16)>,
+    pub stack_depth: usize,
+    pub stack_overflow: StackOverflow,
+    pub random: Generator,
+    pub vip_interpreter: Option<Vec<u8>>,
+    pub vip_monitor: Option<Vec<u8>>,
+    pub unknown_opcodes: UnknownOpcodes,
+    pub scale: u32,
+    pub scale_mode: ScaleMode,
+    pub fullscreen: bool,
+    pub rotation: Rotation,
+    pub theme: Theme,
+    pub palette: Palette,
+    pub phosphor: Option<f32>,
+    pub keymap: Keymap,
+    pub audio: Audio,
+}
+
+impl Settings {
+    /// Set up the chip with the settings
+    pub fn configure(&self, chip: &mut Cpu) {
+        chip.quirks = self.quirks;
+        chip.platform = self.platform;
+        chip.display = Display::for_platform(self.platform);
+        chip.load_address = self.load_address;
+        chip.font = self.font.clone();
+        chip.font_address = self.font_address;
+        chip.memory = vec![0; self.memory_size];
+        chip.reserved = self.reserved.clone();
+        chip.stack = Stack::new(self.stack_depth, self.stack_overflow);
+        chip.unknown_opcodes = self.unknown_opcodes;
+        chip.rng = match (self.random, &self.vip_interpreter) {
+            // `resolve` made sure there is an interpreter
+            (Generator::Vip, Some(interpreter)) => Box::new(Vip::new(interpreter).unwrap()),
+            _ => Box::new(Standard::default()),
+        };
+        chip.keypad.keymap = self.keymap;
+    }
+
+    /// A machine of the backend with the program loaded, ready to run it.
+    /// With `saved_flags` the RPL user flags come from the file of the ROM and are saved there,
+    /// otherwise they start out empty and are gone once the machine is
+    pub fn machine(&self, program: &[u8], seed: u64, saved_flags: bool) -> Result<Box<dyn Machine>, String> {
+        match (self.backend, &self.vip_interpreter) {
+            // `resolve` made sure there is an interpreter
+            (Backend::Vip, Some(interpreter)) => {
+                // The color board isn't emulated, only the resolution is taken from the platform
+                let (width, height) = self.platform.display_size();
+                let display = Display::with_size(width, height);
+                let mut vip = CosmacVip::new(self.memory_size, interpreter.clone(), self.vip_monitor.clone(), display);
+                vip.keypad().keymap = self.keymap;
+                vip.seed(seed);
+                vip.load_program(self.load_address, &self.reserved, program)?;
+                Ok(Box::new(vip))
+            },
+            _ => {
+                let mut chip = Cpu::new();
+                self.configure(&mut chip);
+                // The flags the ROM saved the last time it ran
+                if saved_flags {
+                    if let Some(path) = Flags::path(&rom::hash(program)) {
+                        chip.flags = Flags::load(path);
+                    }
+                }
+                chip.seed(seed);
+                chip.load_program(program)?;
+                Ok(Box::new(chip))
+            },
+        }
+    }
+}
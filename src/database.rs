@@ -0,0 +1,233 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::config::{Config, QuirksConfig};
+use crate::platform::Platform;
+
+/// The programs that come with wrack, in the format of the chip-8-database
+/// <https://github.com/chip-8/chip-8-database>
+const BUNDLED: &str = include_str!("../db/programs.json");
+/// The platforms of the chip-8-database, with their speed and quirks
+const BUNDLED_PLATFORMS: &str = include_str!("../db/platforms.json");
+
+/// What is known about ROMs, found by their SHA-1
+#[derive(Debug, Default)]
+pub struct Database {
+    programs: Vec<Program>,
+    /// The hash of every ROM and the program it belongs to
+    hashes: HashMap<String, usize>,
+    /// The platforms by their id
+    platforms: HashMap<String, PlatformInfo>,
+}
+
+/// A platform of the database, what ROMs for it get unless they say otherwise
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlatformInfo {
+    pub id: String,
+    /// Instructions per frame
+    pub default_tickrate: Option<u32>,
+    #[serde(default)]
+    pub quirks: DatabaseQuirks,
+}
+
+/// A program, which can have multiple ROMs (versions, fixes, ports)
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Program {
+    pub title: String,
+    pub release: Option<String>,
+    #[serde(default)]
+    pub authors: Vec<String>,
+    pub roms: HashMap<String, RomInfo>,
+}
+
+/// A single ROM of a program, everything is optional
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RomInfo {
+    /// The platforms it runs on, the best one first
+    #[serde(default)]
+    pub platforms: Vec<String>,
+    /// Quirks that differ from the ones of a platform
+    #[serde(default)]
+    pub quirky_platforms: HashMap<String, DatabaseQuirks>,
+    /// Instructions per frame
+    pub tickrate: Option<u32>,
+    pub start_address: Option<u16>,
+    /// What the keypad keys do in the game, like `"up": 5`
+    #[serde(default)]
+    pub keys: HashMap<String, u8>,
+    pub colors: Option<Colors>,
+}
+
+/// The quirks as the database names them, the ones wrack doesn't have are left out
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DatabaseQuirks {
+    pub shift: Option<bool>,
+    pub memory_leave_i_unchanged: Option<bool>,
+    pub wrap: Option<bool>,
+    pub jump: Option<bool>,
+    pub logic: Option<bool>,
+    pub vblank: Option<bool>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Colors {
+    /// Background first, then the colors of the planes
+    #[serde(default)]
+    pub pixels: Vec<String>,
+}
+
+/// A ROM found in the database
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub program: Program,
+    pub rom: RomInfo,
+    /// The platform the ROM runs on, if the database knows it
+    pub platform_info: Option<PlatformInfo>,
+}
+
+impl Database {
+    /// The database that comes with wrack
+    pub fn bundled() -> Database {
+        let mut database = Database::parse(BUNDLED).expect("the bundled database is broken");
+        database.platforms = parse_platforms(BUNDLED_PLATFORMS).expect("the bundled platforms are broken");
+        database
+    }
+
+    /// Read a database: either the `programs.json` of the chip-8-database
+    /// or the `database` directory it is in, together with its `platforms.json`
+    pub fn load(path: &Path) -> Result<Database, String> {
+        let file = if path.is_dir() { path.join("programs.json") } else { path.to_path_buf() };
+        let text = fs::read_to_string(&file)
+            .map_err(|e| format!("could not read {}: {}", file.display(), e))?;
+        let mut database = Database::parse(&text).map_err(|e| format!("could not parse {}: {}", file.display(), e))?;
+        let platforms = file.with_file_name("platforms.json");
+        if platforms.is_file() {
+            let text = fs::read_to_string(&platforms)
+                .map_err(|e| format!("could not read {}: {}", platforms.display(), e))?;
+            database.platforms = parse_platforms(&text).map_err(|e| format!("could not parse {}: {}", platforms.display(), e))?;
+        }
+        Ok(database)
+    }
+
+    fn parse(text: &str) -> Result<Database, serde_json::Error> {
+        let programs: Vec<Program> = serde_json::from_str(text)?;
+        let mut hashes = HashMap::new();
+        for (index, program) in programs.iter().enumerate() {
+            for hash in program.roms.keys() {
+                hashes.insert(hash.to_lowercase(), index);
+            }
+        }
+        Ok(Database { programs, hashes, platforms: HashMap::new() })
+    }
+
+    /// Put another database on top of this one, its entries win
+    pub fn merge(&mut self, other: Database) {
+        let offset = self.programs.len();
+        for (hash, index) in other.hashes {
+            self.hashes.insert(hash, index + offset);
+        }
+        self.programs.extend(other.programs);
+        self.platforms.extend(other.platforms);
+    }
+
+    /// The bundled database with the one from `path` on top, if there is one
+    pub fn with_override(path: Option<&Path>) -> Result<Database, String> {
+        let mut database = Database::bundled();
+        if let Some(path) = path {
+            database.merge(Database::load(path)?);
+        }
+        Ok(database)
+    }
+
+    /// Look up a ROM by its SHA-1
+    pub fn lookup(&self, hash: &str) -> Option<Entry> {
+        let program = &self.programs[*self.hashes.get(hash)?];
+        let rom = program
+            .roms
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(hash))
+            .map(|(_, rom)| rom)?;
+        let mut entry = Entry { program: program.clone(), rom: rom.clone(), platform_info: None };
+        entry.platform_info = entry.platform().and_then(|(id, _)| self.platforms.get(id)).cloned();
+        Some(entry)
+    }
+}
+
+fn parse_platforms(text: &str) -> Result<HashMap<String, PlatformInfo>, serde_json::Error> {
+    let platforms: Vec<PlatformInfo> = serde_json::from_str(text)?;
+    Ok(platforms.into_iter().map(|platform| (platform.id.clone(), platform)).collect())
+}
+
+impl Entry {
+    /// The first platform of the ROM that wrack can run, by its name in the database
+    pub fn platform(&self) -> Option<(&str, Platform)> {
+        self.rom
+            .platforms
+            .iter()
+            .find_map(|id| platform_from_id(id).map(|platform| (id.as_str(), platform)))
+    }
+
+    /// The settings the database knows, as a layer of the config
+    pub fn to_config(&self) -> Config {
+        let info = self.platform_info.as_ref();
+        let mut config = Config {
+            ipf: self.rom.tickrate.or(info.and_then(|info| info.default_tickrate)),
+            load_address: self.rom.start_address,
+            // XO-CHIP games can have up to 16 colors, wrack only shows 4 of them
+            colors: self.rom.colors.as_ref().map(|colors| colors.pixels.iter().take(4).cloned().collect()),
+            ..Config::default()
+        };
+        if let Some((id, platform)) = self.platform() {
+            config.platform = Some(platform);
+            // The quirks of the platform, with the ones that differ for the ROM on top
+            let mut quirks = info.map(|info| info.quirks.to_config()).unwrap_or_default();
+            if let Some(quirky) = self.rom.quirky_platforms.get(id) {
+                quirks = quirks.merged(&quirky.to_config());
+            }
+            config.quirks = quirks;
+        }
+        config
+    }
+
+    /// The keys the game uses, like `up 5`, sorted by keypad key
+    pub fn key_hints(&self) -> Vec<(&str, u8)> {
+        let mut hints: Vec<(&str, u8)> = self.rom.keys.iter().map(|(name, key)| (name.as_str(), *key)).collect();
+        hints.sort_by_key(|(name, key)| (*key, *name));
+        hints
+    }
+}
+
+impl DatabaseQuirks {
+    fn to_config(&self) -> QuirksConfig {
+        QuirksConfig {
+            shift: self.shift,
+            load_store: self.memory_leave_i_unchanged,
+            vf_reset: self.logic,
+            jump: self.jump,
+            clip: self.wrap.map(|wrap| !wrap),
+            display_wait: self.vblank,
+            // The database doesn't know about it
+            vf_order: None,
+        }
+    }
+}
+
+/// The platforms of the database that behave like one of wrack
+fn platform_from_id(id: &str) -> Option<Platform> {
+    match id {
+        "originalChip8" | "hybridVIP" => Some(Platform::Vip),
+        "modernChip8" => Some(Platform::Modern),
+        "chip8x" => Some(Platform::Chip8x),
+        // MEGA-CHIP is SUPER-CHIP until it turns its own mode on,
+        // the quirks still come from the platform the ROM is made for
+        "chip48" | "superchip1" | "superchip" | "megachip8" => Some(Platform::Megachip),
+        _ => None,
+    }
+}
@@ -0,0 +1,54 @@
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+/// XO-CHIP has 16 flags, SUPER-CHIP only 8 of them
+pub const FLAGS: usize = 16;
+
+/// The RPL user flags that `Fx75` and `Fx85` save registers to and load them from.
+/// The HP48 kept them between runs and games keep their high scores there,
+/// so they are kept in a file for every ROM.
+#[derive(Debug, Clone, Default)]
+pub struct Flags {
+    values: [u8; FLAGS],
+    /// Where they are saved, nowhere for flags that are gone once the ROM stops
+    path: Option<PathBuf>,
+}
+
+impl Flags {
+    /// `$XDG_DATA_HOME/wrack/flags/<hash>`, or wherever data goes on the system,
+    /// for the ROM with the SHA-1 `hash`
+    pub fn path(hash: &str) -> Option<PathBuf> {
+        dirs::data_dir().map(|dir| dir.join("wrack").join("flags").join(hash))
+    }
+
+    /// The flags that were saved to `path`, all 0 if nothing was saved yet.
+    /// A file that can't be read is left alone until the flags are saved again
+    pub fn load(path: PathBuf) -> Flags {
+        let mut values = [0; FLAGS];
+        if let Ok(saved) = fs::read(&path) {
+            for (value, byte) in values.iter_mut().zip(saved) {
+                *value = byte;
+            }
+        }
+        Flags { values, path: Some(path) }
+    }
+
+    pub fn values(&self) -> &[u8; FLAGS] {
+        &self.values
+    }
+
+    /// `Fx75`, set the first flags to `values` and write all of them to the file
+    pub fn save(&mut self, values: &[u8]) -> io::Result<()> {
+        self.values[..values.len()].copy_from_slice(values);
+        match &self.path {
+            Some(path) => {
+                if let Some(dir) = path.parent() {
+                    fs::create_dir_all(dir)?;
+                }
+                fs::write(path, self.values)
+            },
+            None => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,139 @@
+use winit::event::VirtualKeyCode;
+
+/// Which key of the keyboard presses which key of the keypad.
+/// `keys[i]` is the keyboard key for the keypad key `i`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Keymap {
+    pub keys: [VirtualKeyCode; 16],
+}
+
+impl Keymap {
+    /// The keypad keys 0 to F in rows, the way wrack always did it:
+    /// 1 2 3 4
+    /// Q W E R
+    /// A S D F
+    /// Y X C V
+    pub fn linear() -> Keymap {
+        use VirtualKeyCode::*;
+        Keymap {
+            keys: [
+                Key1, Key2, Key3, Key4,
+                Q, W, E, R,
+                A, S, D, F,
+                Y, X, C, V,
+            ],
+        }
+    }
+
+    /// The layout of the COSMAC VIP keypad put onto the same keys:
+    /// 1 2 3 C        1 2 3 4
+    /// 4 5 6 D   on   Q W E R
+    /// 7 8 9 E        A S D F
+    /// A 0 B F        Z X C V
+    pub fn cosmac() -> Keymap {
+        use VirtualKeyCode::*;
+        Keymap {
+            keys: [
+                X, Key1, Key2, Key3,
+                Q, W, E, A,
+                S, D, Z, C,
+                Key4, R, F, V,
+            ],
+        }
+    }
+
+    /// The second keypad of CHIP-8X on the number block:
+    /// the digits for 0 to 9, then / * - + Enter and the decimal point for A to F
+    pub fn numpad() -> Keymap {
+        use VirtualKeyCode::*;
+        Keymap {
+            keys: [
+                Numpad0, Numpad1, Numpad2, Numpad3,
+                Numpad4, Numpad5, Numpad6, Numpad7,
+                Numpad8, Numpad9, NumpadDivide, NumpadMultiply,
+                NumpadSubtract, NumpadAdd, NumpadEnter, NumpadDecimal,
+            ],
+        }
+    }
+
+    /// Either the name of a keymap (`linear` or `cosmac`)
+    /// or the 16 keys for the keypad keys 0 to F, separated by commas
+    pub fn parse(text: &str) -> Result<Keymap, String> {
+        match text {
+            "linear" => return Ok(Keymap::linear()),
+            "cosmac" => return Ok(Keymap::cosmac()),
+            _ => (),
+        }
+
+        let names: Vec<&str> = text.split(',').map(str::trim).collect();
+        if names.len() != 16 {
+            return Err(format!("a keymap needs 16 keys, got {}", names.len()));
+        }
+        let mut keys = Keymap::linear().keys;
+        for (key, name) in keys.iter_mut().zip(names) {
+            *key = key_from_name(name).ok_or_else(|| format!("unknown key {}", name))?;
+        }
+        Ok(Keymap { keys })
+    }
+
+    /// The name of the keyboard key for a keypad key, like `W`
+    pub fn key_name(&self, index: u8) -> String {
+        let name = format!("{:?}", self.keys[(index & 0xF) as usize]);
+        match name.strip_prefix("Key") {
+            Some(digit) => digit.to_string(),
+            None => name,
+        }
+    }
+
+    /// The keypad key the keyboard key is mapped to
+    pub fn index_of(&self, key: VirtualKeyCode) -> Option<u8> {
+        self.keys.iter().position(|k| *k == key).map(|index| index as u8)
+    }
+}
+
+impl Default for Keymap {
+    fn default() -> Keymap {
+        Keymap::linear()
+    }
+}
+
+/// Letters, digits and a couple of named keys
+pub fn key_from_name(name: &str) -> Option<VirtualKeyCode> {
+    use VirtualKeyCode::*;
+    const LETTERS: [VirtualKeyCode; 26] = [
+        A, B, C, D, E, F, G, H, I, J, K, L, M,
+        N, O, P, Q, R, S, T, U, V, W, X, Y, Z,
+    ];
+    const DIGITS: [VirtualKeyCode; 10] = [
+        Key0, Key1, Key2, Key3, Key4, Key5, Key6, Key7, Key8, Key9,
+    ];
+    const NUMPAD: [VirtualKeyCode; 10] = [
+        Numpad0, Numpad1, Numpad2, Numpad3, Numpad4,
+        Numpad5, Numpad6, Numpad7, Numpad8, Numpad9,
+    ];
+
+    let lower = name.to_ascii_lowercase();
+    let mut chars = lower.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        return match c {
+            'a'..='z' => Some(LETTERS[c as usize - 'a' as usize]),
+            '0'..='9' => Some(DIGITS[c as usize - '0' as usize]),
+            _ => None,
+        };
+    }
+    if let Some(digit) = lower.strip_prefix("numpad").and_then(|d| d.parse::<usize>().ok()) {
+        return NUMPAD.get(digit).copied();
+    }
+    match lower.as_str() {
+        "space" => Some(Space),
+        "up" => Some(Up),
+        "down" => Some(Down),
+        "left" => Some(Left),
+        "right" => Some(Right),
+        "enter" | "return" => Some(Return),
+        "tab" => Some(Tab),
+        "shift" => Some(LShift),
+        "ctrl" => Some(LControl),
+        _ => None,
+    }
+}
@@ -0,0 +1,101 @@
+use serde::{Deserialize, Serialize};
+
+use crate::recorder::Sound;
+use crate::scheduler::Scheduler;
+use crate::{Cpu, Display, Keypad, State};
+
+/// What runs the ROM
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    /// wrack runs the CHIP-8 instructions itself
+    #[default]
+    Chip8,
+    /// A COSMAC VIP runs its original CHIP-8 interpreter, which runs the ROM
+    Vip,
+}
+
+/// What the frontends need from a machine that runs a ROM
+pub trait Machine {
+    /// Run the instructions of a frame, returns how many ran
+    fn run_frame(&mut self, scheduler: &mut Scheduler) -> u32;
+
+    fn display(&self) -> &Display;
+
+    fn keypad(&mut self) -> &mut Keypad;
+
+    /// What plays right now
+    fn sound(&self) -> Sound<'_>;
+
+    /// Start the program over, the memory stays as it is
+    fn soft_reset(&mut self);
+
+    /// Clear the memory, load the program again and start it
+    fn hard_reset(&mut self);
+
+    fn toggle_pause(&mut self);
+
+    fn state(&self) -> State;
+
+    /// The registers of a stopped machine, to see where it is
+    fn describe(&self) -> String;
+
+    /// What went wrong since the last time, for the frontend to show where it fits
+    fn take_warnings(&mut self) -> Vec<String>;
+}
+
+impl Machine for Cpu {
+    fn run_frame(&mut self, scheduler: &mut Scheduler) -> u32 {
+        scheduler.run_frame(self)
+    }
+
+    fn display(&self) -> &Display {
+        &self.display
+    }
+
+    fn keypad(&mut self) -> &mut Keypad {
+        &mut self.keypad
+    }
+
+    /// A sound of MEGA-CHIP plays instead of the beep
+    fn sound(&self) -> Sound<'_> {
+        match &self.sample {
+            Some(sample) => Sound::Sample(sample),
+            None if self.st > 0 => Sound::Beep,
+            None => Sound::Silent,
+        }
+    }
+
+    fn soft_reset(&mut self) {
+        Cpu::soft_reset(self);
+    }
+
+    fn hard_reset(&mut self) {
+        Cpu::hard_reset(self);
+    }
+
+    fn toggle_pause(&mut self) {
+        Cpu::toggle_pause(self);
+    }
+
+    fn state(&self) -> State {
+        self.state
+    }
+
+    /// The registers and the subroutine calls
+    fn describe(&self) -> String {
+        let mut text = format!("  pc {:#05X}  i {:#05X}  dt {}  st {}\n ", self.pc, self.i, self.dt, self.st);
+        for (index, value) in self.v.iter().enumerate() {
+            text += &format!(" v{:X} {:02X}", index, value);
+        }
+        text += "\n";
+        for frame in self.call_stack().iter().rev() {
+            text += &format!("  in {:#05X}, returns to {:#05X}\n", frame.subroutine, frame.return_address);
+        }
+        text
+    }
+
+    fn take_warnings(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.warnings)
+    }
+}
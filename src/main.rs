@@ -2,79 +2,186 @@ use pixels::{Pixels, SurfaceTexture};
 use winit::dpi::LogicalSize;
 use winit::event::{Event, WindowEvent, VirtualKeyCode, KeyboardInput, ElementState};
 use winit::event_loop::{ControlFlow, EventLoop};
-use winit::window::WindowBuilder;
+use winit::window::{Fullscreen, WindowBuilder};
 use winit_input_helper::WinitInputHelper;
-use rand::Rng;
-
-use std::io;
-use std::io::prelude::*;
-use std::fs::File;
-use std::env;
+use clap::Parser;
+
+use std::collections::HashSet;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::time::Instant;
+
+mod analysis;
+mod assembler;
+mod cdp1802;
+mod cli;
+mod config;
+mod database;
+mod disasm;
+mod flags;
+mod font;
+mod keymap;
+mod machine;
+mod megachip;
+mod octo;
+mod palette;
+mod phosphor;
+mod platform;
+mod random;
+mod recorder;
+mod rom;
+mod scheduler;
+mod stack;
+mod timing;
+mod tui;
+mod vip;
+mod vp590;
+mod watch;
+
+use cli::{BenchArgs, Cli, Command, ConfigArgs, DisasmArgs, InfoArgs, RunArgs};
+use config::{Config, Settings};
+use database::{Database, Entry};
+use flags::Flags;
+use font::{Font, BIG_GLYPH, SMALL_GLYPH};
+use keymap::Keymap;
+use machine::Machine;
+use megachip::{Blend, Framebuffer, Sample};
+use palette::{Color, Palette, Theme};
+use phosphor::Phosphor;
+use platform::{Platform, Quirks};
+use random::{Random, Standard};
+use octo::OctoOptions;
+use recorder::{Audio, Recorder, VideoFormat};
+use rom::Rom;
+use scheduler::{Scheduler, FRAME_RATE};
+use stack::{Frame, Stack};
+use vp590::ColorBoard;
+use watch::Watcher;
 
 const WIDTH: usize = 64;
 const HEIGHT: usize = 32;
+/// The high resolution of SUPER-CHIP
+const HIRES: (usize, usize) = (128, 64);
 
-pub static FONT_SET: [u8; 80] = [
-    0xF0, 0x90, 0x90, 0x90, 0xF0, // 1
-    0x20, 0x60, 0x20, 0x20, 0x70, // 2
-    0xF0, 0x10, 0xF0, 0x80, 0xF0, // 3
-    0xF0, 0x10, 0xF0, 0x10, 0xF0, // 4
-    0x90, 0x90, 0xF0, 0x10, 0x10, // 5
-    0xF0, 0x80, 0xF0, 0x10, 0xF0, // 6
-    0xF0, 0x80, 0xF0, 0x90, 0xF0, // 7
-    0xF0, 0x10, 0x20, 0x40, 0x40, // 8
-    0xF0, 0x90, 0xF0, 0x90, 0xF0, // 9
-    0xF0, 0x90, 0xF0, 0x10, 0xF0, // A
-    0xF0, 0x90, 0xF0, 0x90, 0x90, // B
-    0xE0, 0x90, 0xE0, 0x90, 0xE0, // C
-    0xF0, 0x80, 0x80, 0x80, 0xF0, // S
-    0xE0, 0x90, 0x90, 0x90, 0xE0, // E
-    0xF0, 0x80, 0xF0, 0x80, 0xF0, // F
-    0xF0, 0x80, 0xF0, 0x80, 0x80  // G
-];
-
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone)]
 pub struct Display {
-    pub memory: [u8; 2048],
+    pub memory: Vec<u8>,
+    width: usize,
+    height: usize,
+    pub colors: Colors,
+}
+
+/// Where the colors of the display come from
+#[derive(Debug, Clone)]
+pub enum Colors {
+    /// The palette of the window, by the bitplanes of a pixel
+    Palette,
+    /// The color board of CHIP-8X
+    ColorBoard(ColorBoard),
+    /// MEGA-CHIP mode, where the pixels are the indices of colors the program loaded
+    /// into a framebuffer of its own
+    Megachip(Box<Framebuffer>),
 }
 
 impl Display {
 
     ///
     pub fn new() -> Display {
-        Display { memory: [0; 2048] }
+        Display::with_size(WIDTH, HEIGHT)
+    }
+
+    /// A display in another resolution than 64x32
+    pub fn with_size(width: usize, height: usize) -> Display {
+        Display { memory: vec![0; width * height], width, height, colors: Colors::Palette }
+    }
+
+    /// The display of a platform, in its resolution and with its colors.
+    /// MEGA-CHIP starts out like CHIP-8 until the program turns on MEGA-CHIP mode
+    pub fn for_platform(platform: Platform) -> Display {
+        let (width, height) = platform.display_size();
+        match platform {
+            Platform::Chip8x => {
+                let mut display = Display::with_size(width, height);
+                display.colors = Colors::ColorBoard(ColorBoard::new(height));
+                display
+            },
+            Platform::Megachip => Display::new(),
+            _ => Display::with_size(width, height),
+        }
+    }
+
+    /// The 256x192 display of MEGA-CHIP mode
+    pub fn megachip() -> Display {
+        let mut display = Display::with_size(megachip::WIDTH, megachip::HEIGHT);
+        display.colors = Colors::Megachip(Box::default());
+        display
     }
 
     ///
     pub fn set_pixel(&mut self, x: usize, y: usize, on: bool) {
-        self.memory[x + y * WIDTH] = on as u8;
+        self.memory[x + y * self.width] = on as u8;
     }
 
     ///
     pub fn get_pixel(&mut self, x: usize, y: usize) -> bool {
-        self.memory[x + y * WIDTH] == 1
+        self.memory[x + y * self.width] == 1
+    }
+
+    /// Horizontal resolution in pixels
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// Vertical resolution in pixels
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// The raw value of a pixel, one bit for each bitplane
+    pub fn get_planes(&self, x: usize, y: usize) -> u8 {
+        self.memory[x + y * self.width]
+    }
+
+    /// The color of a pixel and whether it is set.
+    /// The color board of CHIP-8X and the framebuffer of MEGA-CHIP
+    /// decide the colors instead of the palette
+    pub fn color(&self, x: usize, y: usize, palette: &Palette) -> (Color, bool) {
+        let planes = self.get_planes(x, y);
+        match &self.colors {
+            Colors::Palette => (palette.color(planes), planes != 0),
+            Colors::ColorBoard(colors) => (colors.color(x, y, planes != 0), planes != 0),
+            Colors::Megachip(framebuffer) => (framebuffer.color(x, y, palette.color(0)), framebuffer.is_drawn(x, y)),
+        }
     }
 
     /// Clear Screen instruction
     pub fn cls(&mut self) {
-        for x in 0..WIDTH {
-            for y in 0..HEIGHT {
-                self.set_pixel(x, y, false);
-            }
-        }
+        self.memory.fill(0);
     }
 
     /// Draw Screen instruction
-    pub fn draw(&mut self, x: usize, y: usize, sprite: &[u8]) -> bool {
-        let rows = sprite.len();
+    /// With `clip` set the parts of the sprite that go over the edge are cut off,
+    /// otherwise they wrap around to the other side.
+    pub fn draw(&mut self, x: usize, y: usize, sprite: &[u8], clip: bool) -> bool {
+        self.draw_wide(x, y, sprite, 1, clip)
+    }
+
+    /// Draw a sprite with rows of `bytes` bytes, the 16x16 sprites of SUPER-CHIP have 2
+    pub fn draw_wide(&mut self, x: usize, y: usize, sprite: &[u8], bytes: usize, clip: bool) -> bool {
         let mut collision = false;
-        for j in 0..rows {
-            let row = sprite[j];
-            for i in 0..8 {
-                let new_value = row >> (7 - i) & 0x01;
+        let (width, height) = (self.width, self.height);
+        // The starting position always wraps around
+        let (x, y) = (x % width, y % height);
+        for (j, row) in sprite.chunks(bytes).enumerate() {
+            for i in 0..row.len() * 8 {
+                let new_value = row[i / 8] >> (7 - i % 8) & 0x01;
+                if clip && (x + i >= width || y + j >= height) {
+                    continue;
+                }
                 if new_value == 1 {
-                    let xi = (x + i) % WIDTH;
-                    let yj = (y + j) % HEIGHT;
+                    let xi = (x + i) % width;
+                    let yj = (y + j) % height;
                     let old_value = self.get_pixel(xi, yj);
                     if old_value {
                         collision = true;
@@ -85,6 +192,15 @@ impl Display {
         }
         return  collision;
     }
+
+    /// Move the picture `dx` pixels to the right and `dy` down (SUPER-CHIP `00Cn`, `00FB`, `00FC`),
+    /// what comes in from the edges is blank
+    pub fn scroll(&mut self, dx: isize, dy: isize) {
+        scroll(&mut self.memory, self.width, dx, dy, 0);
+        if let Colors::Megachip(framebuffer) = &mut self.colors {
+            framebuffer.scroll(dx, dy);
+        }
+    }
 }
 
 /// The Keypad implementation ...
@@ -94,16 +210,21 @@ impl Display {
 /// 1 2 3 4
 /// Q W E R
 /// A S D F
-/// Z X C V
-/// Make it scan codes instead of strings
+/// Y X C V
+/// Other layouts can be set with the keymap
 pub struct Keypad {
     pub keys : [bool; 16],
+    pub keymap: Keymap,
+    // the second keypad of CHIP-8X, on the number block
+    pub second_keys: [bool; 16],
 }
 
 impl Keypad {
     pub fn new() -> Keypad {
         Keypad {
-            keys: [false; 16]
+            keys: [false; 16],
+            keymap: Keymap::linear(),
+            second_keys: [false; 16],
         }
     }
 
@@ -119,6 +240,10 @@ impl Keypad {
         self.keys[index as usize]
     }
 
+    pub fn is_second_key_down(&self, index: u8) -> bool {
+        self.second_keys[index as usize]
+    }
+
     pub fn process_inputs(&mut self, event: &WindowEvent) -> bool {
         match event {
             WindowEvent::KeyboardInput {
@@ -134,164 +259,346 @@ impl Keypad {
         }
     }
 
-    fn keypad_input(&mut self, key: VirtualKeyCode, state: ElementState) -> bool {
-        let amount = if state == ElementState::Pressed { true } else { false };
-        match amount {
-            true => {
-                match key {
-                    VirtualKeyCode::Key1 => {
-                        self.keys[0] = true;
-                        true
-                    },
-                    VirtualKeyCode::Key2 => {
-                        self.keys[1] = true;
-                        true
-                    },
-                    VirtualKeyCode::Key3 => {
-                        self.keys[2] = true;
-                        true
-                    },
-                    VirtualKeyCode::Key4 => {
-                        self.keys[3] = true;
-                        true
-                    },
-                    VirtualKeyCode::Q    => {
-                        self.keys[4] = true;
-                        true
-                    },
-                    VirtualKeyCode::W    => {
-                        self.keys[5] = true;
-                        true
-                    },
-                    VirtualKeyCode::E    => {
-                        self.keys[6] = true;
-                        true
-                    },
-                    VirtualKeyCode::R    => {
-                        self.keys[7] = true;
-                        true
-                    },
-                    VirtualKeyCode::A    => {
-                        self.keys[8] = true;
-                        true
-                    },
-                    VirtualKeyCode::S    => {
-                        self.keys[9] = true;
-                        true
-                    },
-                    VirtualKeyCode::D    => {
-                        self.keys[10] = true;
-                        true
-                    },
-                    VirtualKeyCode::F    => {
-                        self.keys[11] = true;
-                        true
-                    },
-                    VirtualKeyCode::Y    => {
-                        self.keys[12] = true;
-                        true
-                    },
-                    VirtualKeyCode::X    => {
-                        self.keys[13] = true;
-                        true
-                    },
-                    VirtualKeyCode::C    => {
-                        self.keys[14] = true;
-                        true
-                    },
-                    VirtualKeyCode::V    => {
-                        self.keys[15] = true;
-                        true
-                    },
-                    _ => {
-                        for i in 0..self.keys.len() {
-                            self.keys[i] = false;
-                        }
-                        false
-                    }
+    /// A key of the keyboard went down or up, returns whether it is on one of the keypads
+    pub fn keypad_input(&mut self, key: VirtualKeyCode, state: ElementState) -> bool {
+        match self.keymap.index_of(key) {
+            Some(index) => {
+                match state {
+                    ElementState::Pressed => self.key_down(index),
+                    ElementState::Released => self.key_up(index),
                 }
-            }
-            false => false,
+                true
+            },
+            // The keys of the first keypad win over the number block
+            None => match Keymap::numpad().index_of(key) {
+                Some(index) => {
+                    self.second_keys[index as usize] = state == ElementState::Pressed;
+                    true
+                },
+                None => false,
+            },
         }
     }
 }
 
 pub struct Cpu {
-    // index register
-    pub i: u16,
+    // index register, 24 bits with MEGA-CHIP
+    pub i: u32,
     // program counter
     pub pc: u16,
-    // memory
-    pub memory: [u8; 4096],
+    // memory, 4K for most platforms
+    pub memory: Vec<u8>,
     // register
     pub v: [u8; 16],
     // peripherals
     pub keypad: Keypad,
     pub display: Display,
-    // stack
-    pub stack: [u16; 16],
-    // stack pointer
-    pub sp: u8,
+    // the subroutine calls, with their return addresses
+    pub stack: Stack,
     // delay timer
     pub dt: u8,
     // sound timer
     pub st: u8,
+    // behaviour of the instructions that differ between interpreters
+    pub quirks: Quirks,
+    // the interpreter, for the instructions only some of them have
+    pub platform: Platform,
+    // where the program is loaded
+    pub load_address: u16,
+    // the digits for Fx29 and Fx30, loaded at the font address
+    pub font: Font,
+    pub font_address: u16,
+    // ranges of memory (inclusive) that belong to the interpreter, no program goes there
+    pub reserved: Vec<(u16, u16)>,
+    // source of the random numbers, seeded the same every time unless seeded otherwise
+    pub rng: Box<dyn Random>,
+    // the loaded program, to load it again on a hard reset
+    pub program: Vec<u8>,
+    // running, paused or stopped by a fault
+    pub state: State,
+    // with the display wait quirk, set by a drawing until the frame is over
+    pub waiting_for_vblank: bool,
+    // what to do about instructions that aren't known
+    pub unknown_opcodes: UnknownOpcodes,
+    // the addresses of the unknown instructions that were logged already
+    logged: HashSet<u16>,
+    // what went wrong and wasn't shown yet, the frontend takes them with `take_warnings`
+    warnings: Vec<String>,
+    // the sound MEGA-CHIP is playing
+    pub sample: Option<Sample>,
+    // the RPL user flags of Fx75 and Fx85, they stay over resets
+    pub flags: Flags,
+}
+
+/// How many addresses of unknown instructions are logged at most
+const MAX_LOGGED: usize = 8;
+
+/// What the chip is up to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum State {
+    Running,
+    /// Stopped, but it can go on. With the reason, unless it was paused by the user
+    Paused(Option<Fault>),
+    /// Stopped because of a fault, until it is reset
+    Halted(Fault),
+}
+
+/// What the chip does about instructions it doesn't know
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UnknownOpcodes {
+    /// Skip them like they weren't there
+    Ignore,
+    /// Skip them, but tell where they are (once for every address)
+    #[default]
+    Log,
+    /// Pause the chip on them, going on from there skips them
+    Pause,
+    /// Stop the chip with an error
+    Halt,
+}
+
+/// Something a program did that stops the chip
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fault {
+    /// A call with no room left on the stack
+    StackOverflow { address: u16, depth: usize },
+    /// A return without a call
+    StackUnderflow { address: u16 },
+    /// An instruction that isn't known on the platform
+    UnknownOpcode { address: u16, opcode: u16 },
+    /// `0nnn`, a call of a machine code routine of the computer the interpreter runs on
+    MachineCode { address: u16, routine: u16 },
+    /// `00FD`, the program ended itself (SUPER-CHIP)
+    Exit { address: u16 },
+}
+
+impl Fault {
+    /// Where the instruction that caused it is
+    pub fn address(&self) -> u16 {
+        match self {
+            Fault::StackOverflow { address, .. }
+            | Fault::StackUnderflow { address }
+            | Fault::UnknownOpcode { address, .. }
+            | Fault::MachineCode { address, .. }
+            | Fault::Exit { address } => *address,
+        }
+    }
+}
+
+impl std::fmt::Display for Fault {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Fault::StackOverflow { address, depth } => {
+                write!(f, "stack overflow at {:#05X}, the stack only has room for {} calls", address, depth)
+            },
+            Fault::StackUnderflow { address } => write!(f, "return without a call at {:#05X}", address),
+            Fault::UnknownOpcode { address, opcode } => write!(f, "unknown instruction {:04X} at {:#05X}", opcode, address),
+            Fault::MachineCode { address, routine } => {
+                write!(f, "{:#05X} calls the machine code routine at {:#05X}, which can't be run", address, routine)
+            },
+            Fault::Exit { address } => write!(f, "the program ended at {:#05X}", address),
+        }
+    }
 }
 
 impl Cpu {
+    /// A machine as it is after being turned on: the font in memory,
+    /// ready to run a program from the load address
     pub fn new() -> Cpu {
-        Cpu {
+        let layout = Platform::default().layout();
+        let mut chip = Cpu {
             i: 0,
             pc: 0,
-            memory: [0; 4096],
+            memory: vec![0; layout.memory_size],
             v: [0; 16],
             keypad: Keypad::new(),
             display: Display::new(),
-            stack: [0; 16],
-            sp: 0,
+            stack: Stack::default(),
             dt: 0,
             st: 0,
-        }
+            quirks: Quirks::default(),
+            platform: Platform::default(),
+            load_address: layout.load_address,
+            font: Font::default(),
+            font_address: layout.font_address,
+            reserved: layout.reserved,
+            rng: Box::new(Standard::default()),
+            program: Vec::new(),
+            state: State::Running,
+            waiting_for_vblank: false,
+            unknown_opcodes: UnknownOpcodes::default(),
+            logged: HashSet::new(),
+            warnings: Vec::new(),
+            sample: None,
+            flags: Flags::default(),
+        };
+        chip.hard_reset();
+        chip
+    }
+
+    /// Make the random numbers repeatable
+    pub fn seed(&mut self, seed: u64) {
+        self.rng.seed(seed);
     }
 
-    pub fn reset(&mut self) {
+    /// Start the program over, like the reset button: registers, stack, timers
+    /// and the display are cleared, the memory is left as it is
+    pub fn soft_reset(&mut self) {
         self.i = 0;
         // Historically the program was loaded in memory after the chip-8 interpreter
         // that is why it starts at 0x200 or 512
-        self.pc = 0x200;
-        self.memory = [0; 4096];
+        self.pc = self.entry_point();
         self.v = [0; 16];
-        self.stack = [0; 16];
-        self.sp = 0;
+        self.stack.clear();
+        self.state = State::Running;
+        self.waiting_for_vblank = false;
+        self.logged.clear();
         self.dt = 0;
         self.st = 0;
-        self.display.cls();
-        // Load in the font
-        for i in 0..80 {
-            self.memory[i] = FONT_SET[i];
+        self.sample = None;
+        // Also back to the colors of the color board and out of MEGA-CHIP mode
+        self.display = Display::for_platform(self.platform);
+    }
+
+    /// Where the program starts. Hi-res programs start with `1260`, a jump into the 1802 code
+    /// that makes the interpreter show 64x64 pixels. That code can't run here,
+    /// so they start behind it, where the jump leads in the end
+    fn entry_point(&self) -> u16 {
+        let load_address = self.load_address;
+        if self.platform == Platform::Hires && self.memory.get(load_address as usize..load_address as usize + 2) == Some(&[0x12, 0x60]) {
+            return load_address + 0xC0;
         }
+        load_address
+    }
+
+    /// Turn the machine off and on again: on top of the soft reset the memory is cleared,
+    /// the font put back and the program loaded again
+    pub fn hard_reset(&mut self) {
+        self.memory.fill(0);
+        // Load in the font, the big digits right after the small ones.
+        // Whatever doesn't fit is cut off, `load_program` makes sure that doesn't happen
+        let big_font_address = self.big_font_address();
+        write_memory(&mut self.memory, self.font_address as usize, &self.font.small);
+        write_memory(&mut self.memory, big_font_address as usize, &self.font.big);
+        write_memory(&mut self.memory, self.load_address as usize, &self.program);
+        self.soft_reset();
+    }
+
+    /// The big font follows right after the small one.
+    /// Like `I` it can be past 0xFFFF with more than 64K of memory
+    pub fn big_font_address(&self) -> u32 {
+        self.font_address as u32 + self.font.small.len() as u32
+    }
+
+    /// Put a program that is already read into memory and start it.
+    /// It has to fit into memory without touching the font or the memory of the interpreter.
+    pub fn load_program(&mut self, program: &[u8]) -> Result<(), String> {
+        let start = self.load_address as usize;
+        let end = start + program.len();
+        let overlaps = |first: usize, last: usize| start <= last && end > first;
+        if end > self.memory.len() {
+            return Err(format!(
+                "the program is {} bytes too big, the memory ends at {:#05X}",
+                end - self.memory.len(),
+                self.memory.len() - 1
+            ));
+        }
+        let font = self.font_address as usize;
+        if !self.font.small.is_empty() && overlaps(font, font + self.font.len() - 1) {
+            return Err(format!("the program would overwrite the font at {:#05X}", font));
+        }
+        if let Some((first, last)) = self.reserved.iter().find(|(first, last)| overlaps(*first as usize, *last as usize)) {
+            return Err(format!(
+                "the program ({:#05X} - {:#05X}) overlaps the memory of the interpreter at {:#05X} - {:#05X}",
+                start, end.saturating_sub(1), first, last
+            ));
+        }
+        self.program = program.to_vec();
+        self.hard_reset();
+        Ok(())
+    }
+
+    /// The subroutine calls the program is in, the innermost last
+    pub fn call_stack(&self) -> &[Frame] {
+        self.stack.frames()
+    }
+
+    /// Stop the chip at the instruction that is running
+    fn halt(&mut self, fault: Fault) {
+        self.pc = fault.address();
+        self.state = State::Halted(fault);
     }
 
-    pub fn load_rom(&mut self, file_path: String) -> io::Result<u16> {
-        let mut f = File::open(file_path)?;
-        let mut buffer = Vec::new();
+    /// Deal with an instruction that can't be run, the way `unknown_opcodes` says
+    fn unknown(&mut self, fault: Fault) {
+        match self.unknown_opcodes {
+            UnknownOpcodes::Ignore => (),
+            // A program that runs off into empty memory would fill the screen with them
+            UnknownOpcodes::Log if self.logged.len() < MAX_LOGGED => {
+                if self.logged.insert(fault.address()) {
+                    self.warnings.push(fault.to_string());
+                    if self.logged.len() == MAX_LOGGED {
+                        self.warnings.push("not telling about more of them".to_string());
+                    }
+                }
+            },
+            UnknownOpcodes::Log => (),
+            UnknownOpcodes::Pause => self.state = State::Paused(Some(fault)),
+            UnknownOpcodes::Halt => self.halt(fault),
+        }
+    }
 
-        // read the whole file
-        f.read_to_end(&mut buffer)?;
-        for i in 0..buffer.len() {
-            // println!("i is {i}");
-            // println!("{:>08b}", self.memory[i]);
-            // Start loading it at 0x200 / 512
-            self.memory[0x200 + i] = buffer[i];
+    /// Put the result of an 8xyn instruction into Vx and its flag into VF.
+    /// With x = F only the one that is written last stays
+    fn set_with_flag(&mut self, x: usize, value: u8, flag: u8) {
+        if self.quirks.vf_order {
+            self.v[0xF] = flag;
+            self.v[x] = value;
+        } else {
+            self.v[x] = value;
+            self.v[0xF] = flag;
         }
-        Ok(buffer.len() as u16)
     }
 
+    /// Pause a running chip or let a paused one go on, a halted one stays halted
+    pub fn toggle_pause(&mut self) {
+        self.state = match self.state {
+            State::Running => State::Paused(None),
+            State::Paused(_) => State::Running,
+            halted => halted,
+        };
+    }
+
+    /// The instruction at the pc, which runs next
+    pub fn opcode(&self) -> u16 {
+        read_word(&self.memory, self.pc)
+    }
+
+    /// Run one instruction, unless the chip is paused or halted
     pub fn execute_cycle(&mut self) {
-        let opcode: u16 = read_word(self.memory, self.pc);
+        if self.state != State::Running {
+            return;
+        }
+        let opcode = self.opcode();
         self.process_opcode(opcode);
     }
 
+    /// Count down the delay and sound timer, 60 times a second.
+    /// They stand still while the chip isn't running
+    pub fn tick_timers(&mut self) {
+        if self.state != State::Running {
+            return;
+        }
+        self.dt = self.dt.saturating_sub(1);
+        self.st = self.st.saturating_sub(1);
+        if let Some(sample) = &mut self.sample {
+            if !sample.advance(1.0 / 60.0) {
+                self.sample = None;
+            }
+        }
+    }
+
     fn process_opcode(&mut self, opcode: u16) {
         // Extract the parameters
         // second nibble
@@ -319,7 +626,7 @@ impl Cpu {
         let op_4 = opcode & 0x000F;
 
         // increment the counter
-        self.pc += 2;
+        self.pc = self.pc.wrapping_add(2);
 
         // printline for help with opcodes
         // println!("{:X}, {:X}, {:X}, {:X}", op_1, op_2, op_3, op_4);
@@ -329,15 +636,121 @@ impl Cpu {
         match (op_1, op_2, op_3, op_4) {
             // CLS
             // Clear the display
-            (0, 0, 0xE, 0) => self.display.cls(),
+            // In MEGA-CHIP mode what was drawn is shown and the next picture starts
+            (0, 0, 0xE, 0) => {
+                if let Colors::Megachip(framebuffer) = &mut self.display.colors {
+                    framebuffer.present();
+                }
+                self.display.cls();
+            },
+
+            // MEGA-CHIP: turn MEGA-CHIP mode off or on, both clear the display
+            (0, 0, 1, 0) if self.platform == Platform::Megachip => self.display = Display::new(),
+            (0, 0, 1, 1) if self.platform == Platform::Megachip => self.display = Display::megachip(),
+
+            // LDHI I, nnnnnn (MEGA-CHIP)
+            // Set I to a 24 bit address, the low byte of the instruction
+            // and the word after it, which is skipped
+            (0, 1, _, _) if self.platform == Platform::Megachip => {
+                self.i = (kk as u32) << 16 | read_word(&self.memory, self.pc) as u32;
+                self.pc = self.pc.wrapping_add(2);
+            },
+
+            // LDPAL nn (MEGA-CHIP)
+            // Load nn colors from I, 4 bytes of ARGB each, into the palette from color 1 on
+            (0, 2, _, _) if self.platform == Platform::Megachip => {
+                let colors = read_wrapping(&self.memory, self.i as usize, kk as usize * 4);
+                if let Colors::Megachip(framebuffer) = &mut self.display.colors {
+                    framebuffer.load_palette(&colors);
+                }
+            },
+
+            // SPRW nn, SPRH nn (MEGA-CHIP)
+            // Set the width or the height of the sprites, 0 is 256
+            (0, 3, _, _) | (0, 4, _, _) if self.platform == Platform::Megachip => {
+                let size = if kk == 0 { 256 } else { kk as usize };
+                if let Colors::Megachip(framebuffer) = &mut self.display.colors {
+                    if op_2 == 3 {
+                        framebuffer.sprite_width = size;
+                    } else {
+                        framebuffer.sprite_height = size;
+                    }
+                }
+            },
+
+            // ALPHA nn (MEGA-CHIP)
+            // Set the opacity of the screen
+            (0, 5, _, _) if self.platform == Platform::Megachip => {
+                if let Colors::Megachip(framebuffer) = &mut self.display.colors {
+                    framebuffer.alpha = kk;
+                }
+            },
+
+            // DIGISND n (MEGA-CHIP)
+            // Play the sound at I, over and over for n = 0, once for n = 1
+            (0, 6, 0, _) if self.platform == Platform::Megachip => {
+                self.sample = Some(Sample::read(&self.memory, self.i as usize, n == 0));
+            },
+
+            // STOPSND (MEGA-CHIP)
+            (0, 7, 0, 0) if self.platform == Platform::Megachip => self.sample = None,
+
+            // BMODE n (MEGA-CHIP)
+            // Set how sprites are blended: normal, 25%, 50%, 75%, added or multiplied
+            (0, 8, 0, _) if self.platform == Platform::Megachip && Blend::from_n(n).is_some() => {
+                if let (Some(blend), Colors::Megachip(framebuffer)) = (Blend::from_n(n), &mut self.display.colors) {
+                    framebuffer.blend = blend;
+                }
+            },
+
+            // CCOL nn (MEGA-CHIP)
+            // Drawing over the color nn sets VF
+            (0, 9, _, _) if self.platform == Platform::Megachip => {
+                if let Colors::Megachip(framebuffer) = &mut self.display.colors {
+                    framebuffer.collision_color = kk;
+                }
+            },
+
+            // SCD n (SUPER-CHIP)
+            // Scroll the display down n pixels
+            (0, 0, 0xC, _) if self.platform == Platform::Megachip => self.display.scroll(0, n as isize),
+
+            // SCR, SCL (SUPER-CHIP)
+            // Scroll the display 4 pixels to the right or to the left
+            (0, 0, 0xF, 0xB) if self.platform == Platform::Megachip => self.display.scroll(4, 0),
+            (0, 0, 0xF, 0xC) if self.platform == Platform::Megachip => self.display.scroll(-4, 0),
+
+            // EXIT (SUPER-CHIP)
+            // The program is done, the chip stops
+            (0, 0, 0xF, 0xD) if self.platform == Platform::Megachip => self.halt(Fault::Exit { address: self.pc.wrapping_sub(2) }),
+
+            // LOW, HIGH (SUPER-CHIP)
+            // Switch to 64x32 or 128x64 pixels, which clears the display.
+            // Both leave MEGA-CHIP mode like 0010 does
+            (0, 0, 0xF, 0xE) if self.platform == Platform::Megachip => self.display = Display::new(),
+            (0, 0, 0xF, 0xF) if self.platform == Platform::Megachip => {
+                let (width, height) = HIRES;
+                self.display = Display::with_size(width, height);
+            },
+
+            // The hi-res interpreter clears the screen with its own routine
+            (0, 2, 3, 0) if self.platform == Platform::Hires => self.display.cls(),
+
+            // CHIP-8X: next background color
+            // Blue, black, green and red, then blue again
+            (0, 2, 0xA, 0) if self.platform == Platform::Chip8x => {
+                if let Colors::ColorBoard(colors) = &mut self.display.colors {
+                    colors.next_background();
+                }
+            },
 
             // RET
             // Return from a subroutine
-            // Set the pc on the address of the top of the stack,f
-            // then decrement the stack by 1
-            (0, 0, 0xE, 0xE) => {
-                self.pc = self.stack[self.sp as usize];
-                self.sp -= 1;
+            // Take the innermost call off the stack
+            // and set the pc to its return address
+            (0, 0, 0xE, 0xE) => match self.stack.pop() {
+                Some(frame) => self.pc = frame.return_address,
+                None => self.halt(Fault::StackUnderflow { address: self.pc.wrapping_sub(2) }),
             },
 
             // JP addr
@@ -346,12 +759,15 @@ impl Cpu {
 
             // CALL addr
             // Call subroutine at nnn
-            // Increment the sp, then put current PC on the top of the stack,
+            // Put the current PC on top of the stack as the return address,
             // Then set the PC to nnn
+            // What happens when the stack is full depends on the overflow policy
             (0x2, _, _, _) => {
-                self.sp += 1;
-                self.stack[self.sp as usize] = self.pc;
-                self.pc = nnn;
+                if self.stack.push(Frame { subroutine: nnn, return_address: self.pc }) {
+                    self.pc = nnn;
+                } else {
+                    self.halt(Fault::StackOverflow { address: self.pc.wrapping_sub(2), depth: self.stack.depth });
+                }
             }
 
             // SE vx, byte"
@@ -360,7 +776,7 @@ impl Cpu {
             // if they are equal increment pc by 2
             (0x3, _, _, _) => {
                 if vx == kk {
-                    self.pc += 2;
+                    self.pc = self.pc.wrapping_add(2);
                 }
             },
 
@@ -370,7 +786,7 @@ impl Cpu {
             // if they are not equal increment pc by 2
             (0x4, _, _, _) => {
                 if vx != kk {
-                    self.pc += 2;
+                    self.pc = self.pc.wrapping_add(2);
                 }
             },
 
@@ -378,12 +794,18 @@ impl Cpu {
             // Skip next instruction if Vx = Vy.
             // Compares register Vx to register Vy and
             // if they are equal increment tpc by 2
-            (0x5, _, _, _) => {
+            (0x5, _, _, 0x0) => {
                 if vx == vy {
-                    self.pc += 2;
+                    self.pc = self.pc.wrapping_add(2);
                 }
             },
 
+            // CHIP-8X: ADD Vx, Vy
+            // Both nibbles are added on their own, without a carry from the lower one
+            (0x5, _, _, 0x1) if self.platform == Platform::Chip8x => {
+                self.v[x] = (vx & 0xF0).wrapping_add(vy & 0xF0) | (vx.wrapping_add(vy) & 0x0F);
+            },
+
             // LD Vx, byte
             // Put value kk into register Vx
             (0x6, _, _, _) => self.v[x] = kk,
@@ -400,17 +822,32 @@ impl Cpu {
             // Set Vx = Vx OR Vy
             // Perform bitwise OR on the values of Vx and Vy and
             // then store the result in Vx
-            (0x8, _, _, 0x1) => self.v[x] |= vy,
+            (0x8, _, _, 0x1) => {
+                self.v[x] |= vy;
+                if self.quirks.vf_reset {
+                    self.v[0xF] = 0;
+                }
+            },
             // AND Vx. Vy
             // Set Vx = Vx AND Vy
             // Perform bitwise AND on the values of Vx and Vy and
             // then store the result in Vx
-            (0x8, _, _, 0x2) => self.v[x] &= vy,
+            (0x8, _, _, 0x2) => {
+                self.v[x] &= vy;
+                if self.quirks.vf_reset {
+                    self.v[0xF] = 0;
+                }
+            },
             // XOR Vx, Vy
             // Set Vx = VX XOR Vy
             // Performs bitwise exclusive OR on the values of Vx and Vy and
             // then store the result in Vx
-            (0x8, _, _, 0x3) => self.v[x] ^= vy,
+            (0x8, _, _, 0x3) => {
+                self.v[x] ^= vy;
+                if self.quirks.vf_reset {
+                    self.v[0xF] = 0;
+                }
+            },
             // ADD Vx, Vy
             // Set Vx = Vx + Vy, set VF = carry
             // The values of Vx and Vy are added together
@@ -418,11 +855,7 @@ impl Cpu {
             // Only the lowest 8 bits of the result are kept and stored in Vx
             (0x8, _, _, 0x4) => {
                 let (res, overflow) = vx.overflowing_add(vy);
-                match overflow {
-                    true => self.v[0xF] = 1,
-                    false => self.v[0xF] = 0,
-                }
-                self.v[x] = res;
+                self.set_with_flag(x, res, overflow as u8);
             },
             // SUB Vx, Vy
             // Set Vx = Vx - Vy, set VF = not borrow
@@ -430,44 +863,30 @@ impl Cpu {
             // Then Vy is szbtracted from Vx and the result stored in Vx
             (0x8, _, _, 0x5) => {
                 let (res, overflow) = vx.overflowing_sub(vy);
-                match overflow {
-                    true => self.v[0xF] = 0,
-                    false => self.v[0xF] = 1,
-                }
-                self.v[x] = res;
+                self.set_with_flag(x, res, !overflow as u8);
             },
             // SHR Vx {, Vy}
             // Set Vx = Vx SHR 1
             // If the least-significant bit of Vx is 1,
             // then VF is set to 1, otherwise 0, then Vx is divided by 2
-            (0x8, _, _, 0x6) => {
-                self.v[0xF] = vx & 0x1;
-                self.v[x] >>= 1;
-            },
+            // The COSMAC VIP shifted Vy and put the result into Vx
+            (0x8, _, _, 0x6) if !self.quirks.shift => self.set_with_flag(x, vy >> 1, vy & 0x1),
+            (0x8, _, _, 0x6) => self.set_with_flag(x, vx >> 1, vx & 0x1),
             // SUBN Vx, Vy
             // Set Vx = Vy - Vx, set VF = NOT borrow
             // If Vy > Vy, then VF is 1, otherwise 0
             // Then Vx is subtracted from Vy, and the result stored in Vx
             (0x8, _, _, 0x7) => {
                 let (res, overflow) = vy.overflowing_sub(vx);
-                match overflow {
-                    true => self.v[0xF] = 0,
-                    false => self.v[0xF] = 1,
-                }
-                self.v[x] = res;
+                self.set_with_flag(x, res, !overflow as u8);
             },
             // SHL Vx  {, Vy}
             // Set Vx 0 Vx SHL 1
             // If the most-significant bit of Vx is 1,
             // then VF is set to 1, otherwise 0, then Vx is multiplied by 2
-            (0x8, _, _, 0xE) => {
-                let msb = vx & 0x80 == 0x80;
-                match msb {
-                    true => self.v[0xF] = 1,
-                    false => self.v[0xF] = 0
-                }
-                self.v[x] <<= 1;
-            },
+            // The COSMAC VIP shifted Vy and put the result into Vx
+            (0x8, _, _, 0xE) if !self.quirks.shift => self.set_with_flag(x, vy << 1, vy >> 7),
+            (0x8, _, _, 0xE) => self.set_with_flag(x, vx << 1, vx >> 7),
 
             // SNE Vx, Vy
             // Skip next instruction if Vx != Vy
@@ -475,18 +894,36 @@ impl Cpu {
             // if they are not equal increment pc by 2
             (0x9, _ , _, 0x0) => {
                 if vx != vy {
-                    self.pc += 2;
+                    self.pc = self.pc.wrapping_add(2);
                 }
             },
 
             // LD I, addr
             // Set I = nnn
             // The value of register I is set to nnn
-            (0xA, _, _, _) => self.i = nnn,
+            (0xA, _, _, _) => self.i = nnn as u32,
 
             // JV V0, addr
             // Jump to location nnn + V0
             // pc is set to nnn plus the value of V0
+            // CHIP-48 and SUPER-CHIP took the register from the x nibble
+            // CHIP-8X has no jump with an offset, it colors the display instead
+            // Bxy0 colors zones of 8x4 pixels, Vx tells which columns and the next register
+            // which rows, Bxyn colors n rows of the 8 pixels at Vx, Vx+1
+            // The color is in Vy
+            (0xB, _, _, 0) if self.platform == Platform::Chip8x => {
+                let next = self.v[(x + 1) & 0xF];
+                if let Colors::ColorBoard(colors) = &mut self.display.colors {
+                    colors.color_zones(vx, next, vy);
+                }
+            },
+            (0xB, _, _, _) if self.platform == Platform::Chip8x => {
+                let next = self.v[(x + 1) & 0xF];
+                if let Colors::ColorBoard(colors) = &mut self.display.colors {
+                    colors.color_rows(vx, next, n, vy);
+                }
+            },
+            (0xB, _, _, _) if self.quirks.jump => self.pc = nnn + vx as u16,
             (0xB, _, _, _) => self.pc = nnn + self.v[0] as u16,
 
             // RND Vx, byte
@@ -495,10 +932,7 @@ impl Cpu {
             // which is then ANDed with the value of kk
             // the result us stored in Vx
             // (See Instruction 8xy2 for AND)
-            (0xC, _, _, _) => {
-                let rnd = rand::thread_rng().gen_range(0x00..0xFF);
-                self.v[x] = rnd & kk;
-            },
+            (0xC, _, _, _) => self.v[x] = self.rng.byte() & kk,
 
             // DRW Vx, Vy, nibble
             // Display n-byte sprite starting at memory location I at (Vx, Vy), set VF = collision
@@ -509,10 +943,28 @@ impl Cpu {
             // If this causes any pixels to be erased, VF is set to 1, otherwise it is set to 0.
             // If the sprite is positioned so part of it is outside the coordinates of the display, it wraps around to the opposite side of the screen.
             // See instruction 8xy3 for more information on XOR, and section 2.4, Display, for more information on the Chip-8 screen and sprites.
+            // In MEGA-CHIP mode the sprite at I has a byte for the color index of every pixel
+            // and the size set with 03nn and 04nn, n doesn't matter
+            (0xD, _, _, _) if matches!(self.display.colors, Colors::Megachip(_)) => {
+                let Display { memory, colors: Colors::Megachip(framebuffer), .. } = &mut self.display else {
+                    unreachable!();
+                };
+                let sprite = read_wrapping(&self.memory, self.i as usize, framebuffer.sprite_width * framebuffer.sprite_height);
+                let collision = framebuffer.draw(memory, vx as usize, vy as usize, &sprite);
+                self.v[0xF] = collision as u8;
+            },
+            // SUPER-CHIP: Dxy0 draws a sprite of 16x16 pixels, 2 bytes a row
+            (0xD, _, _, 0) if self.platform == Platform::Megachip => {
+                let sprite = read_wrapping(&self.memory, self.i as usize, 32);
+                let collision = self.display.draw_wide(vx as usize, vy as usize, &sprite, 2, self.quirks.clip);
+                self.v[0xF] = collision as u8;
+            },
             (0xD, _, _, _) => {
-                let collision = self.display.draw(vx as usize, vy as usize,
-                    &self.memory[self.i as usize .. (self.i + n as u16) as  usize]);
+                let sprite = read_wrapping(&self.memory, self.i as usize, n as usize);
+                let collision = self.display.draw(vx as usize, vy as usize, &sprite, self.quirks.clip);
                 self.v[0xF] = if collision { 1 } else { 0 };
+                // The VIP draws in the vertical blank, the chip has to wait for the next one
+                self.waiting_for_vblank = self.quirks.display_wait;
             },
 
             // SKP Vx
@@ -521,7 +973,7 @@ impl Cpu {
             // Vx is currently in the down position, pc is increased by 2
             (0xE, _, 0x9, 0xE) => {
                 if self.keypad.is_key_down(vx) {
-                    self.pc += 2;
+                    self.pc = self.pc.wrapping_add(2);
                 }
             },
 
@@ -531,7 +983,20 @@ impl Cpu {
             // Vx is currently in the up position, pc is increased by 2
             (0xE, _, 0xA, 0x1) => {
                 if !self.keypad.is_key_down(vx) {
-                    self.pc += 2;
+                    self.pc = self.pc.wrapping_add(2);
+                }
+            },
+
+            // CHIP-8X: skip the next instruction if the key Vx of the second keypad is pressed,
+            // or if it is not pressed
+            (0xE, _, 0xF, 0x2) if self.platform == Platform::Chip8x => {
+                if self.keypad.is_second_key_down(vx & 0xF) {
+                    self.pc = self.pc.wrapping_add(2);
+                }
+            },
+            (0xE, _, 0xF, 0x5) if self.platform == Platform::Chip8x => {
+                if !self.keypad.is_second_key_down(vx & 0xF) {
+                    self.pc = self.pc.wrapping_add(2);
                 }
             },
 
@@ -544,11 +1009,11 @@ impl Cpu {
             // Wait for a key press, store the value of the key in Vx
             // All execution stops until a key is pressed, them the value of the key is stored in Vx
             (0xF, _, 0x0, 0xA) => {
-                self.pc -= 2;
+                self.pc = self.pc.wrapping_sub(2);
                 for (i, key) in self.keypad.keys.iter().enumerate() {
                     if *key == true {
                         self.v[x] = i as u8;
-                        self.pc += 2;
+                        self.pc = self.pc.wrapping_add(2);
                     }
                 }
             },
@@ -567,13 +1032,18 @@ impl Cpu {
             // Set I = I + Vx
             // The values of I and Vx are added
             // the result is stored in I
-            (0xF, _, 0x1, 0xF) => self.i += vx as u16,
+            (0xF, _, 0x1, 0xE) => self.i += vx as u32,
 
             // LD F, Vx
             // Set I = location of sprite for digit Vx
             // The value of I is set to the location for the hexadecimal sprite
-            // corresponding to the value of Vx.
-            (0xF, _, 0x2, 0x9) => self.i = vx as u16 * 5,
+            // corresponding to the value of Vx, only the low nibble counts.
+            (0xF, _, 0x2, 0x9) => self.i = self.font_address as u32 + (vx & 0xF) as u32 * SMALL_GLYPH as u32,
+
+            // LD HF, Vx (SUPER-CHIP)
+            // Set I = location of the big 8x10 sprite for digit Vx
+            // Fonts with only 0 to 9 point past them for bigger digits, just like SUPER-CHIP did
+            (0xF, _, 0x3, 0x0) => self.i = self.big_font_address() + (vx & 0xF) as u32 * BIG_GLYPH as u32,
 
             // LD B, Vx
             // Store BCD representation of Vx in memory locations I, I+1 and I+2
@@ -582,93 +1052,424 @@ impl Cpu {
             // and tens digit at location I+1
             // and ones digit at location I+2
             (0xF, _, 0x3, 0x3) => {
-                self.memory[self.i as usize] = vx / 100;
-                self.memory[self.i as usize + 1] = (vx / 10) % 10;
-                self.memory[self.i as usize + 2] = (vx % 100) % 10;
+                write_wrapping(&mut self.memory, self.i as usize, &[vx / 100, (vx / 10) % 10, (vx % 100) % 10]);
             },
 
             // LD [I], Vx
             // Store registers V0 through Vx from memory starting at location I
             // Copy the values of register V0 through Vx into memory,
             // starting at address in I
-            (0xF, _, 0x5, 0x5) => self.memory[(self.i as usize)..(self.i + x as u16 + 1) as usize]
-                .copy_from_slice(&self.v[0..(x as usize + 1)]),
+            // The COSMAC VIP left I pointing after the last register
+            (0xF, _, 0x5, 0x5) => {
+                write_wrapping(&mut self.memory, self.i as usize, &self.v[0..(x as usize + 1)]);
+                if !self.quirks.load_store {
+                    self.i += x as u32 + 1;
+                }
+            },
 
             // LD Vx, [I]
             // Read registers V0 through Vx from memory starting at location I
             // Read the values from memory starting at location I
             // into registers V0 through Vx
-            (0xF, _, 0x6, 0x5) => self.v[0..(x as usize + 1)]
-            .copy_from_slice(&self.memory[(self.i as usize)..(self.i + x as u16 + 1) as usize]),
+            // The COSMAC VIP left I pointing after the last register
+            (0xF, _, 0x6, 0x5) => {
+                self.v[0..(x as usize + 1)]
+                    .copy_from_slice(&read_wrapping(&self.memory, self.i as usize, x + 1));
+                if !self.quirks.load_store {
+                    self.i += x as u32 + 1;
+                }
+            },
+
+            // LD R, Vx (SUPER-CHIP)
+            // Store registers V0 through Vx in the RPL user flags
+            // SUPER-CHIP has 8 of them, XO-CHIP 16
+            // They are saved for the next time the ROM runs
+            (0xF, _, 0x7, 0x5) if x < self.platform.flag_count() => {
+                if let Err(e) = self.flags.save(&self.v[..=x]) {
+                    self.warnings.push(format!("could not save the flags: {}", e));
+                }
+            },
+
+            // LD Vx, R (SUPER-CHIP)
+            // Read registers V0 through Vx from the RPL user flags
+            (0xF, _, 0x8, 0x5) if x < self.platform.flag_count() => {
+                self.v[..=x].copy_from_slice(&self.flags.values()[..=x]);
+            },
+
+            // SYS addr
+            // Call the machine code routine at nnn,
+            // which would be code for the processor of the computer, not for chip-8
+            (0, _, _, _) => self.unknown(Fault::MachineCode { address: self.pc.wrapping_sub(2), routine: nnn }),
 
             // There is the possibility
             // to add further instructions for the Super Chip-48
             // It is "just" 10 more opcodes
 
-            // Ignore all other codes...
-            (_, _, _, _) => ()
+            // All other codes go to the unknown opcode policy
+            (_, _, _, _) => self.unknown(Fault::UnknownOpcode { address: self.pc.wrapping_sub(2), opcode }),
+        }
+    }
+}
+
+fn read_word(memory: &[u8], index: u16) -> u16 {
+    let bytes = read_wrapping(memory, index as usize, 2);
+    (bytes[0] as u16) << 8 | (bytes[1] as u16)
+}
+
+/// Move the cells of a picture `width` cells wide `dx` to the right and `dy` down,
+/// what comes in from the edges is `empty`
+fn scroll<T: Copy>(cells: &mut [T], width: usize, dx: isize, dy: isize, empty: T) {
+    let height = (cells.len() / width) as isize;
+    let source = cells.to_vec();
+    for (i, cell) in cells.iter_mut().enumerate() {
+        let x = (i % width) as isize - dx;
+        let y = (i / width) as isize - dy;
+        *cell = if (0..width as isize).contains(&x) && (0..height).contains(&y) {
+            source[x as usize + y as usize * width]
+        } else {
+            empty
+        };
+    }
+}
+
+/// Read `length` bytes from `address` on. Like on a machine with less memory than
+/// the addresses reach, what is past the end is at the start again
+fn read_wrapping(memory: &[u8], address: usize, length: usize) -> Vec<u8> {
+    (address..address + length).map(|at| memory[at % memory.len()]).collect()
+}
+
+/// Write `data` to `address` on, wrapping around the end of memory like `read_wrapping`
+fn write_wrapping(memory: &mut [u8], address: usize, data: &[u8]) {
+    let size = memory.len();
+    for (at, byte) in (address..).zip(data) {
+        memory[at % size] = *byte;
+    }
+}
+
+/// Copy data into memory, cutting off what doesn't fit
+fn write_memory(memory: &mut [u8], address: usize, data: &[u8]) {
+    let start = address.min(memory.len());
+    let end = (start + data.len()).min(memory.len());
+    memory[start..end].copy_from_slice(&data[..end - start]);
+}
+
+/// How far the picture is turned clockwise, for games made for a screen lying on its side
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Rotation {
+    #[default]
+    None,
+    Quarter,
+    Half,
+    ThreeQuarters,
+}
+
+impl Rotation {
+    pub fn from_degrees(degrees: u16) -> Option<Rotation> {
+        match degrees {
+            0 => Some(Rotation::None),
+            90 => Some(Rotation::Quarter),
+            180 => Some(Rotation::Half),
+            270 => Some(Rotation::ThreeQuarters),
+            _ => None,
+        }
+    }
+
+    pub fn degrees(self) -> u16 {
+        match self {
+            Rotation::None => 0,
+            Rotation::Quarter => 90,
+            Rotation::Half => 180,
+            Rotation::ThreeQuarters => 270,
+        }
+    }
+
+    /// Width and height of the picture of a display of `size`
+    pub fn size(self, (width, height): (usize, usize)) -> (usize, usize) {
+        match self {
+            Rotation::None | Rotation::Half => (width, height),
+            Rotation::Quarter | Rotation::ThreeQuarters => (height, width),
+        }
+    }
+
+    /// The pixel of a display of `size` that ends up at `x`, `y` of the picture
+    pub fn source(self, x: usize, y: usize, (width, height): (usize, usize)) -> (usize, usize) {
+        match self {
+            Rotation::None => (x, y),
+            Rotation::Quarter => (y, height - 1 - x),
+            Rotation::Half => (width - 1 - x, height - 1 - y),
+            Rotation::ThreeQuarters => (width - 1 - y, x),
         }
     }
 }
 
-fn read_word(memory: [u8; 4096], index: u16) -> u16 {
-    (memory[index as usize] as u16) << 8 | (memory[(index + 1) as usize] as u16)
+/// How the display is fitted into the window
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ScaleMode {
+    /// Largest whole multiple of the display that fits, pixels stay perfectly square
+    Integer,
+    /// As large as possible while keeping the aspect ratio
+    Fit,
+    /// Fill the whole window
+    Stretch,
 }
 
-struct Window {}
+struct Window {
+    palette: Palette,
+    // The built in theme the palette started from, to cycle from there
+    theme: Theme,
+    // Fading of erased pixels, if turned on
+    phosphor: Option<Phosphor>,
+    // The decay to use when the phosphor is turned on again
+    decay: f32,
+    mode: ScaleMode,
+    rotation: Rotation,
+    // The presented frame in the resolution of the display, turned by the rotation, RGBA
+    frame: Vec<u8>,
+    frame_size: (usize, usize),
+    // Size of the window and of the pixel buffer behind it
+    surface_size: (u32, u32),
+    buffer_size: (u32, u32),
+}
 
 impl Window {
-    fn new() -> Self {
-        Self {}
+    fn new(palette: Palette, theme: Theme, phosphor: Option<f32>, mode: ScaleMode, rotation: Rotation) -> Self {
+        Self {
+            palette,
+            theme,
+            phosphor: phosphor.map(Phosphor::new),
+            decay: phosphor.unwrap_or(phosphor::DEFAULT_DECAY),
+            mode,
+            rotation,
+            frame: vec![0; WIDTH * HEIGHT * 4],
+            frame_size: (WIDTH, HEIGHT),
+            surface_size: (WIDTH as u32, HEIGHT as u32),
+            buffer_size: (WIDTH as u32, HEIGHT as u32),
+        }
     }
 
-    fn draw(&self, frame: &mut [u8], display: Display) {
-        let mut d = display;
-        for(i, pixel) in frame.chunks_exact_mut(4).enumerate() {
-            let x = (i % WIDTH as usize) as usize;
-            let y = (i / WIDTH as usize) as usize;
+    /// Use the palette and theme of a newly loaded ROM
+    fn set_palette(&mut self, palette: Palette, theme: Theme) {
+        self.palette = palette;
+        self.theme = theme;
+    }
+
+    /// Switch over to the next built in theme
+    fn next_theme(&mut self) {
+        self.theme = self.theme.next();
+        self.palette = self.theme.palette();
+    }
+
+    /// Turn the phosphor persistence on or off
+    fn toggle_phosphor(&mut self) {
+        self.phosphor = match self.phosphor.take() {
+            Some(phosphor) => {
+                self.decay = phosphor.decay();
+                None
+            },
+            None => Some(Phosphor::new(self.decay)),
+        };
+    }
 
-            let rgba = if d.get_pixel(x, y) {
-                [0xFF, 0xFF, 0xFF, 0xFF]
+    /// The last drawn frame, before it is scaled into the window
+    fn frame(&self) -> &[u8] {
+        &self.frame
+    }
+
+    /// Width and height of the frame, the resolution of the display as it is turned
+    fn frame_size(&self) -> (usize, usize) {
+        self.frame_size
+    }
+
+    /// Render the display into the frame.
+    /// The frame follows the resolution of the display, the window stays as it is.
+    fn draw(&mut self, display: &Display) {
+        let size = (display.width(), display.height());
+        let (width, height) = self.rotation.size(size);
+        if self.frame_size != (width, height) {
+            self.frame_size = (width, height);
+            self.frame = vec![0; width * height * 4];
+            // The afterglow of another resolution makes no sense
+            if let Some(phosphor) = &mut self.phosphor {
+                *phosphor = Phosphor::new(phosphor.decay());
+            }
+        }
+
+        for(i, pixel) in self.frame.chunks_exact_mut(4).enumerate() {
+            let (x, y) = self.rotation.source(i % width, i / width, size);
+
+            let (mut rgba, lit) = display.color(x, y, &self.palette);
+            // The framebuffer of MEGA-CHIP has no XOR flicker to hide, its pixels don't glow
+            let lit = lit || matches!(display.colors, Colors::Megachip(_));
+            if let Some(phosphor) = &mut self.phosphor {
+                rgba = phosphor.blend(i, rgba, lit);
+            }
+            pixel.copy_from_slice(&rgba);
+        }
+    }
+
+    /// The window has been resized
+    fn resize(&mut self, pixels: &mut Pixels, width: u32, height: u32) {
+        // Minimized, there is nothing to draw into
+        if width == 0 || height == 0 {
+            return;
+        }
+        self.surface_size = (width, height);
+        if let Err(e) = pixels.resize_surface(width, height) {
+            eprintln!("Could not resize the window surface: {}", e);
+        }
+    }
+
+    /// Scale the frame into the pixel buffer of the window
+    fn present(&mut self, pixels: &mut Pixels) -> Result<(), pixels::Error> {
+        let (width, height) = (self.frame_size.0 as u32, self.frame_size.1 as u32);
+        // Integer scaling is done by `pixels` itself,
+        // the other modes need a buffer as large as the window
+        let buffer_size = match self.mode {
+            ScaleMode::Integer => (width, height),
+            ScaleMode::Fit | ScaleMode::Stretch => self.surface_size,
+        };
+        if self.buffer_size != buffer_size {
+            pixels.resize_buffer(buffer_size.0, buffer_size.1)?;
+            self.buffer_size = buffer_size;
+        }
+
+        // Borders in the color of the background
+        let background = self.palette.color(0);
+        pixels.clear_color(to_wgpu_color(background));
+
+        let (buffer_width, buffer_height) = (buffer_size.0 as usize, buffer_size.1 as usize);
+        let (target_width, target_height) = match self.mode {
+            ScaleMode::Integer | ScaleMode::Stretch => (buffer_width, buffer_height),
+            ScaleMode::Fit => {
+                let scale = f64::min(
+                    buffer_width as f64 / width as f64,
+                    buffer_height as f64 / height as f64,
+                );
+                ((width as f64 * scale) as usize, (height as f64 * scale) as usize)
+            },
+        };
+        let offset_x = (buffer_width - target_width) / 2;
+        let offset_y = (buffer_height - target_height) / 2;
+
+        // Nearest neighbour, so the pixels stay sharp
+        let (frame_width, frame_height) = self.frame_size;
+        for (i, pixel) in pixels.frame_mut().chunks_exact_mut(4).enumerate() {
+            let (x, y) = (i % buffer_width, i / buffer_width);
+            let inside = x >= offset_x && x < offset_x + target_width
+                && y >= offset_y && y < offset_y + target_height;
+            let rgba = if inside {
+                let fx = (x - offset_x) * frame_width / target_width;
+                let fy = (y - offset_y) * frame_height / target_height;
+                let start = (fx + fy * frame_width) * 4;
+                &self.frame[start..start + 4]
             } else {
-                [0x00, 0x00, 0x00, 0x00]
+                &background[..]
             };
-            pixel.copy_from_slice(&rgba);
+            pixel.copy_from_slice(rgba);
         }
+
+        pixels.render()
+    }
+}
+
+/// The clear color of the surface is linear, the palette is sRGB
+fn to_wgpu_color(color: Color) -> pixels::wgpu::Color {
+    let linear = |channel: u8| {
+        let c = channel as f64 / 255.0;
+        if c <= 0.04045 {
+            c / 12.92
+        } else {
+            ((c + 0.055) / 1.055).powf(2.4)
+        }
+    };
+    pixels::wgpu::Color {
+        r: linear(color[0]),
+        g: linear(color[1]),
+        b: linear(color[2]),
+        a: color[3] as f64 / 255.0,
+    }
+}
+
+/// Start a recording into a timestamped file or stop the running one.
+/// A new recording has the resolution of the display, `size`
+fn toggle_recording(recorder: &mut Option<Recorder>, format: VideoFormat, size: (usize, usize), audio: Audio) {
+    match recorder.take() {
+        Some(rec) => {
+            let path = rec.path().to_path_buf();
+            match rec.finish() {
+                Ok(()) => println!("Saved recording to {}", path.display()),
+                Err(e) => eprintln!("Could not save recording to {}: {}", path.display(), e),
+            }
+        },
+        None => match Recorder::start_timestamped(format, size.0, size.1, audio) {
+            Ok(rec) => {
+                println!("Recording to {}", rec.path().display());
+                *recorder = Some(rec);
+            },
+            Err(e) => eprintln!("Could not start recording: {}", e),
+        },
+    }
+}
+
+/// Run a freshly loaded chip instead of the old one.
+/// What was changed in the window (theme, phosphor, recording) stays,
+/// only colors that came with a ROM are replaced. The picture is turned the way the new ROM wants it.
+fn switch(chip: &mut Box<dyn Machine>, scheduler: &mut Scheduler, win: &mut Window, loaded: Loaded) {
+    *chip = loaded.chip;
+    scheduler.ipf = loaded.settings.ipf;
+    scheduler.timing = loaded.settings.timing;
+    win.rotation = loaded.settings.rotation;
+    let custom = |palette: Palette, theme: Theme| palette != theme.palette();
+    if custom(loaded.settings.palette, loaded.settings.theme) || custom(win.palette, win.theme) {
+        win.set_palette(loaded.settings.palette, loaded.settings.theme);
     }
 }
 
-fn render (mut chip: Cpu) {
+fn render (mut chip: Box<dyn Machine>, mut scheduler: Scheduler, mut recorder: Option<Recorder>, mut win: Window, mut session: Session, settings: &Settings) {
+    let (scale, fullscreen, audio) = (settings.scale, settings.fullscreen, settings.audio);
     let event_loop = EventLoop::new();
     let mut input = WinitInputHelper::new();
+    // The window is sized after the display of the first ROM,
+    // switching the resolution later on only changes the scaling
+    let (width, height) = win.rotation.size((chip.display().width(), chip.display().height()));
+    let (width, height) = (width as u32, height as u32);
     let window = {
-        let size = LogicalSize::new((WIDTH * 15) as f64, (HEIGHT * 15) as f64);
+        let size = LogicalSize::new((width * scale) as f64, (height * scale) as f64);
+        let min_size = LogicalSize::new(width as f64, height as f64);
         WindowBuilder::new()
             .with_title("Chip-8 Emulator")
             .with_inner_size(size)
-            .with_min_inner_size(size)
-            .with_resizable(false)
+            .with_min_inner_size(min_size)
+            .with_resizable(true)
+            .with_fullscreen(fullscreen.then_some(Fullscreen::Borderless(None)))
             .build(&event_loop)
             .unwrap()
     };
     let mut pixels = {
         let window_size = window.inner_size();
         let surface_texture = SurfaceTexture::new(window_size.width, window_size.height, &window);
-        Pixels::new(WIDTH as u32, HEIGHT as u32, surface_texture).unwrap()
+        Pixels::new(width, height, surface_texture).unwrap()
     };
-    let win = Window::new();
+    let window_size = window.inner_size();
+    win.resize(&mut pixels, window_size.width, window_size.height);
+    // The state that was last told about
+    let mut shown = State::Running;
 
     event_loop.run(move |event, _, control_flow| {
-        *control_flow = ControlFlow::Wait;
-
-        // One of the most important functions ...
-        // letting the cpu execute an cycle
-        chip.execute_cycle();
+        // Sleep until the next frame is due
+        *control_flow = ControlFlow::WaitUntil(scheduler.next_frame());
 
         match event {
             Event::RedrawRequested(window_id) if window_id == window.id() => {
-                win.draw(pixels.frame_mut(), chip.display);
-                if let Err(_) = pixels.render() {
+                win.draw(chip.display());
+                if let Some(rec) = &mut recorder {
+                    if let Err(e) = rec.capture(win.frame(), win.frame_size(), chip.sound()) {
+                        eprintln!("Recording stopped: {}", e);
+                        recorder = None;
+                    }
+                }
+                if let Err(_) = win.present(&mut pixels) {
                     *control_flow = ControlFlow::Exit;
                     return;
                 }
@@ -676,7 +1477,7 @@ fn render (mut chip: Cpu) {
             Event::WindowEvent {
                 ref event,
                 window_id,
-            } if window_id == window.id() && !chip.keypad.process_inputs(event) => {
+            } if window_id == window.id() && !chip.keypad().process_inputs(event) => {
                 match event {
                     WindowEvent::CloseRequested
                     | WindowEvent::KeyboardInput {
@@ -688,9 +1489,22 @@ fn render (mut chip: Cpu) {
                             },
                         ..
                     } => *control_flow = ControlFlow::Exit,
+                    WindowEvent::Resized(size) => win.resize(&mut pixels, size.width, size.height),
+                    WindowEvent::ScaleFactorChanged { new_inner_size, .. } => {
+                        win.resize(&mut pixels, new_inner_size.width, new_inner_size.height)
+                    },
+                    // A ROM dropped onto the window replaces the running one
+                    WindowEvent::DroppedFile(path) => match session.open(path.clone()) {
+                        Ok(loaded) => switch(&mut chip, &mut scheduler, &mut win, loaded),
+                        Err(e) => eprintln!("error: {}", e),
+                    },
                     _ => {}
                 }
             }
+            // Make sure the recording is complete when the window goes away
+            Event::LoopDestroyed if recorder.is_some() => {
+                toggle_recording(&mut recorder, VideoFormat::Gif, win.frame_size(), audio);
+            }
             _ => {}
         }
 
@@ -701,21 +1515,402 @@ fn render (mut chip: Cpu) {
                 return;
             }
 
+            // F9 records a GIF, F10 a Y4M stream,
+            // pressing either of them again stops the recording
+            if input.key_pressed(VirtualKeyCode::F9) {
+                toggle_recording(&mut recorder, VideoFormat::Gif, win.frame_size(), audio);
+            }
+            if input.key_pressed(VirtualKeyCode::F10) {
+                toggle_recording(&mut recorder, VideoFormat::Y4m, win.frame_size(), audio);
+            }
+
+            // F4 cycles through the built in color themes
+            if input.key_pressed(VirtualKeyCode::F4) {
+                win.next_theme();
+            }
+
+            // F7 turns the phosphor persistence on and off
+            if input.key_pressed(VirtualKeyCode::F7) {
+                win.toggle_phosphor();
+            }
+
+            // F5 starts the program over, F6 also reloads it into cleared memory
+            if input.key_pressed(VirtualKeyCode::F5) {
+                chip.soft_reset();
+            }
+            if input.key_pressed(VirtualKeyCode::F6) {
+                chip.hard_reset();
+            }
+
+            // F8 pauses the chip and lets it go on
+            if input.key_pressed(VirtualKeyCode::F8) {
+                chip.toggle_pause();
+            }
+
+            // F11 switches between fullscreen and windowed
+            if input.key_pressed(VirtualKeyCode::F11) {
+                window.set_fullscreen(match window.fullscreen() {
+                    Some(_) => None,
+                    None => Some(Fullscreen::Borderless(None)),
+                });
+            }
+
+            // In --watch mode the ROM starts over whenever it changes
+            if session.changed() {
+                match session.load() {
+                    Ok(loaded) => switch(&mut chip, &mut scheduler, &mut win, loaded),
+                    Err(e) => eprintln!("error: {}", e),
+                }
+            }
+
+            // One of the most important functions ...
+            // letting the cpu execute the cycles of all frames that are due
+            scheduler.update(chip.as_mut());
+            for warning in chip.take_warnings() {
+                eprintln!("warning: {}", warning);
+            }
+            // Tell once when the chip stops
+            if chip.state() != shown {
+                match chip.state() {
+                    State::Halted(fault @ Fault::Exit { .. }) => eprintln!("{}, F5 or F6 starts over", fault),
+                    State::Halted(fault) => eprintln!("error: {}, F5 or F6 starts over", fault),
+                    State::Paused(fault) => {
+                        match fault {
+                            Some(fault) => eprintln!("paused: {}, F8 goes on", fault),
+                            None => eprintln!("paused, F8 goes on"),
+                        }
+                        eprint!("{}", chip.describe());
+                    },
+                    State::Running => (),
+                }
+                shown = chip.state();
+            }
+            *control_flow = ControlFlow::WaitUntil(scheduler.next_frame());
+
             window.request_redraw();
         }
     });
 }
 
-fn main() {
-    let args: Vec<String> = env::args().collect();
+/// Read a ROM with the path in the error message
+fn read_rom(path: &Path) -> Result<Rom, String> {
+    rom::read(path).map_err(|e| format!("could not read {}: {}", path.display(), e))
+}
+
+/// Read a ROM together with the Octo options for it,
+/// either the given ones or the ones next to the ROM
+fn read_rom_with_options(path: &Path, options: Option<&Path>) -> Result<Rom, String> {
+    let mut rom = read_rom(path)?;
+    if let Some(options) = options.map(Path::to_path_buf).or_else(|| rom::options_path(path)) {
+        rom.options = rom.options.merged(&OctoOptions::load(&options)?.to_config());
+    }
+    Ok(rom)
+}
 
-    // Declare the chip
-    let mut chip = Cpu::new();
-    // Reset the chip
-    // chip.reset();
-    // Load an ROM
-    chip.load_rom(args[1].to_string()).unwrap();
+/// Look up a ROM in the database.
+/// The database to use can be set in every layer of the config but the database itself.
+fn lookup(hash: &str, file: &Config, cli: &Config) -> Result<Option<Entry>, String> {
+    let database = Config::layered(file, None, Some(hash), cli).database;
+    Ok(Database::with_override(database.as_deref())?.lookup(hash))
+}
+
+/// Put the config together for a ROM,
+/// with what the database knows about it and the options that came with it
+fn rom_config(rom: &Rom, file: &Config, cli: &Config) -> Result<(Config, Option<Entry>), String> {
+    let hash = rom::hash(&rom.program);
+    let entry = lookup(&hash, file, cli)?;
+    let known = match &entry {
+        Some(entry) => entry.to_config().merged(&rom.options),
+        None => rom.options.clone(),
+    };
+    let config = Config::layered(file, Some(&known), Some(&hash), cli);
+    Ok((config, entry))
+}
+
+/// The ROM that is running and what is needed to load it again,
+/// or another one that is dropped onto the window
+struct Session {
+    file: Config,
+    cli: Config,
+    seed: Option<u64>,
+    path: PathBuf,
+    /// Octo options given on the command line, only for the first ROM
+    options: Option<PathBuf>,
+    /// Set in `--watch` mode
+    watcher: Option<Watcher>,
+}
+
+/// A chip with a ROM loaded and the settings for it
+struct Loaded {
+    chip: Box<dyn Machine>,
+    settings: Settings,
+}
+
+impl Session {
+    /// Read the ROM and set up a chip for it, with the settings that belong to the ROM
+    fn load(&self) -> Result<Loaded, String> {
+        let rom = read_rom_with_options(&self.path, self.options.as_deref())?;
+        let (config, entry) = rom_config(&rom, &self.file, &self.cli)?;
+        let settings = config.resolve()?;
+
+        if let Some(entry) = &entry {
+            println!("{}", entry.program.title);
+            for (action, key) in entry.key_hints() {
+                println!("  {:<8} {:X} ({})", action, key, settings.keymap.key_name(key));
+            }
+        }
+
+        // Declare the chip and load the ROM,
+        // a different game every time unless a seed is given
+        let chip = settings.machine(&rom.program, self.seed.unwrap_or_else(rand::random), true)?;
+        Ok(Loaded { chip, settings })
+    }
+
+    /// Switch to another ROM, watching it instead if in `--watch` mode
+    fn open(&mut self, path: PathBuf) -> Result<Loaded, String> {
+        self.path = path;
+        self.options = None;
+        if self.watcher.is_some() {
+            self.watch()?;
+        }
+        self.load()
+    }
+
+    /// Watch the file the ROM is read from and its options
+    fn watch(&mut self) -> Result<(), String> {
+        if self.path == Path::new("-") {
+            return Err("standard input can't be watched".to_string());
+        }
+        let mut files = vec![rom::source_file(&self.path)];
+        files.extend(self.options.clone().or_else(|| rom::options_path(&self.path)));
+        self.watcher = Some(Watcher::new(&files));
+        Ok(())
+    }
+
+    /// True once after the ROM changed on disk, in `--watch` mode
+    fn changed(&mut self) -> bool {
+        self.watcher.as_mut().is_some_and(Watcher::changed)
+    }
+}
+
+fn run(args: RunArgs, config_path: Option<&Path>) -> Result<(), Box<dyn Error>> {
+    let path = args.rom.clone().ok_or("no ROM given")?;
+    if args.terminal.is_some() && args.record.is_some() {
+        return Err("recording needs the window, not the terminal".into());
+    }
+    let (file, _) = Config::load_file(config_path)?;
+    let mut session = Session {
+        file,
+        cli: args.to_config(),
+        seed: args.machine.seed,
+        path,
+        options: args.machine.octo_options.clone(),
+        watcher: None,
+    };
+    if args.watch {
+        session.watch()?;
+    }
+    let Loaded { chip, settings } = session.load()?;
+
+    if let Some(glyphs) = args.terminal {
+        tui::run(chip, Scheduler::new(settings.ipf, settings.timing), session, &settings, glyphs)
+            .map_err(|e| format!("terminal: {}", e))?;
+        return Ok(());
+    }
+
+    let recorder = match &args.record {
+        Some(path) => {
+            let format = VideoFormat::from_path(path).ok_or("recordings need to end in .gif or .y4m")?;
+            // In the resolution of the platform, MEGA-CHIP starts out in the small one
+            let (width, height) = settings.rotation.size(settings.platform.display_size());
+            let recorder = Recorder::start(path, format, width, height, settings.audio)
+                .map_err(|e| format!("could not record to {}: {}", path.display(), e))?;
+            Some(recorder)
+        },
+        None => None,
+    };
+
+    let win = Window::new(settings.palette, settings.theme, settings.phosphor, settings.scale_mode, settings.rotation);
 
     // render the chip
-    render(chip);
-}
\ No newline at end of file
+    render(chip, Scheduler::new(settings.ipf, settings.timing), recorder, win, session, &settings);
+    Ok(())
+}
+
+fn disasm(args: DisasmArgs, config_path: Option<&Path>) -> Result<(), Box<dyn Error>> {
+    let rom = read_rom_with_options(&args.rom, None)?;
+    let (file, _) = Config::load_file(config_path)?;
+    let cli = Config { load_address: args.load_address, ..Config::default() };
+    // Where the platform the ROM would run on loads it
+    let settings = rom_config(&rom, &file, &cli)?.0.resolve()?;
+    for line in disasm::disassemble_program(&rom.program, settings.load_address) {
+        println!("{}", line);
+    }
+    Ok(())
+}
+
+fn info(args: InfoArgs, config_path: Option<&Path>) -> Result<(), Box<dyn Error>> {
+    let rom = read_rom_with_options(&args.rom, None)?;
+    let hash = rom::hash(&rom.program);
+    let (file, _) = Config::load_file(config_path)?;
+    let cli = Config { load_address: args.load_address, database: args.database.clone(), ..Config::default() };
+    let (config, entry) = rom_config(&rom, &file, &cli)?;
+    // The settings it would run with, for the memory it is loaded into
+    let settings = config.resolve()?;
+    let analysis = analysis::analyze(&rom.program, settings.load_address);
+
+    let start = settings.load_address as usize;
+    let end = start + rom.program.len();
+    println!("file:      {}", args.rom.display());
+    println!("size:      {} bytes", rom.program.len());
+    println!("sha1:      {}", hash);
+    match &entry {
+        Some(entry) => {
+            let mut about = entry.program.authors.join(", ");
+            if let Some(release) = &entry.program.release {
+                about = if about.is_empty() { release.clone() } else { format!("{}, {}", about, release) };
+            }
+            if about.is_empty() {
+                println!("database:  {}", entry.program.title);
+            } else {
+                println!("database:  {} ({})", entry.program.title, about);
+            }
+            if !entry.rom.platforms.is_empty() {
+                println!("           made for {}", entry.rom.platforms.join(", "));
+            }
+        },
+        None => println!("database:  not found"),
+    }
+    print!("platform:  {}", analysis.platform.name());
+    let evidence: Vec<String> = analysis
+        .evidence
+        .iter()
+        .map(|(address, opcode)| format!("{:04X} at {:#05X}", opcode, address))
+        .collect();
+    if evidence.is_empty() {
+        println!(", no instructions of the extensions found");
+    } else {
+        println!(", because of {}", evidence.join(", "));
+    }
+    println!("loaded:    {:#05X} - {:#05X}", start, end.saturating_sub(1));
+    // Loading it checks the memory size, the font and the memory of the interpreter
+    match settings.machine(&rom.program, 0, false) {
+        Ok(_) => println!("fits:      yes"),
+        Err(e) => println!("fits:      no, {}", e),
+    }
+    println!("code:      {}", format_ranges(&analysis.code));
+    println!("data:      {}", format_ranges(&analysis.data));
+    if !analysis.computed_jumps.is_empty() {
+        let jumps: Vec<String> = analysis.computed_jumps.iter().map(|address| format!("{:#05X}", address)).collect();
+        println!("           computed jumps at {}, code behind them might be counted as data", jumps.join(", "));
+    }
+    if analysis.self_modifying.is_empty() {
+        println!("self-modifying: no");
+    }
+    for (address, target) in &analysis.self_modifying {
+        println!("self-modifying: {:#05X} writes to the code at {:#05X}", address, target);
+    }
+    Ok(())
+}
+
+/// Address ranges like `0x200 - 0x21F (32 bytes)`
+fn format_ranges(ranges: &[(u16, u16)]) -> String {
+    if ranges.is_empty() {
+        return "none".to_string();
+    }
+    ranges
+        .iter()
+        .map(|(first, last)| format!("{:#05X} - {:#05X} ({} bytes)", first, last, last - first + 1))
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+fn bench(args: BenchArgs, config_path: Option<&Path>) -> Result<(), Box<dyn Error>> {
+    let rom = read_rom_with_options(&args.rom, args.machine.octo_options.as_deref())?;
+    let (file, _) = Config::load_file(config_path)?;
+    let settings = rom_config(&rom, &file, &args.machine.to_config())?.0.resolve()?;
+
+    // A benchmark must not overwrite the high scores
+    let mut chip = settings.machine(&rom.program, args.machine.seed.unwrap_or_else(rand::random), false)?;
+
+    let mut scheduler = Scheduler::new(settings.ipf, settings.timing);
+    let start = Instant::now();
+    let mut instructions = 0;
+    for frame in 0..args.frames {
+        instructions += chip.run_frame(&mut scheduler) as u64;
+        for warning in chip.take_warnings() {
+            eprintln!("warning: {}", warning);
+        }
+        if let State::Halted(fault) | State::Paused(Some(fault)) = chip.state() {
+            return Err(format!("{} (frame {})", fault, frame + 1).into());
+        }
+    }
+    let elapsed = start.elapsed().as_secs_f64();
+
+    let emulated = args.frames as f64 / FRAME_RATE as f64;
+    println!("frames:        {}", args.frames);
+    println!("instructions:  {}", instructions);
+    println!("time:          {:.3} s", elapsed);
+    println!("speed:         {:.0} instructions/s", instructions as f64 / elapsed);
+    println!("realtime:      {:.1}x", emulated / elapsed);
+    Ok(())
+}
+
+fn print_config(args: ConfigArgs, config_path: Option<&Path>) -> Result<(), Box<dyn Error>> {
+    let (file, path) = Config::load_file(config_path)?;
+    // Where the settings come from, as comments in the TOML
+    let mut notes = Vec::new();
+    match &path {
+        Some(path) => notes.push(format!("config file: {}", path.display())),
+        None => notes.push("no config file, using the defaults".to_string()),
+    }
+
+    let config = match &args.rom {
+        Some(path) => {
+            let rom = read_rom_with_options(path, args.machine.octo_options.as_deref())?;
+            let hash = rom::hash(&rom.program);
+            let section = if file.for_rom(&hash).is_some() { "" } else { ", no section in the config" };
+            notes.push(format!("rom: {} [rom.{}]{}", path.display(), hash, section));
+            let (config, entry) = rom_config(&rom, &file, &args.to_config())?;
+            match entry {
+                Some(entry) => notes.push(format!("database: {}", entry.program.title)),
+                None => notes.push("not in the database".to_string()),
+            }
+            config
+        },
+        None => Config::layered(&file, None, None, &args.to_config()),
+    };
+    // Make sure the values would work
+    let settings = config.resolve()?;
+
+    if args.octo {
+        println!("{}", OctoOptions::from_settings(&settings).to_json()?);
+        return Ok(());
+    }
+    for note in notes {
+        println!("# {}", note);
+    }
+    print!("{}", config.to_toml()?);
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let config_path = cli.config.as_deref();
+    let result = match cli.command {
+        Some(Command::Run(args)) => run(args, config_path),
+        Some(Command::Disasm(args)) => disasm(args, config_path),
+        Some(Command::Info(args)) => info(args, config_path),
+        Some(Command::Bench(args)) => bench(args, config_path),
+        Some(Command::Config(args)) => print_config(args, config_path),
+        None => run(cli.run, config_path),
+    };
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::FAILURE
+        },
+    }
+}
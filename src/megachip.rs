@@ -0,0 +1,214 @@
+use crate::palette::Color;
+
+/// The resolution in MEGA-CHIP mode
+pub const WIDTH: usize = 256;
+pub const HEIGHT: usize = 192;
+
+/// How a sprite is put over what is already drawn (`080n`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Blend {
+    /// The sprite replaces what is below
+    #[default]
+    Normal,
+    /// The sprite shows through at 25%, 50% or 75%
+    Quarter,
+    Half,
+    ThreeQuarters,
+    /// The colors are added up
+    Add,
+    /// The colors are multiplied
+    Multiply,
+}
+
+impl Blend {
+    /// The mode of `080n`, none for the ones that don't exist
+    pub fn from_n(n: u8) -> Option<Blend> {
+        match n {
+            0 => Some(Blend::Normal),
+            1 => Some(Blend::Quarter),
+            2 => Some(Blend::Half),
+            3 => Some(Blend::ThreeQuarters),
+            4 => Some(Blend::Add),
+            5 => Some(Blend::Multiply),
+            _ => None,
+        }
+    }
+
+    fn apply(&self, below: Color, color: Color) -> Color {
+        let mix = |opacity: u32| -> Color {
+            let mut mixed = color;
+            for ((channel, top), bottom) in mixed.iter_mut().zip(color).zip(below).take(3) {
+                *channel = ((top as u32 * opacity + bottom as u32 * (4 - opacity)) / 4) as u8;
+            }
+            mixed
+        };
+        match self {
+            Blend::Normal => color,
+            Blend::Quarter => mix(1),
+            Blend::Half => mix(2),
+            Blend::ThreeQuarters => mix(3),
+            Blend::Add => {
+                let mut sum = color;
+                for ((channel, top), bottom) in sum.iter_mut().zip(color).zip(below).take(3) {
+                    *channel = top.saturating_add(bottom);
+                }
+                sum
+            },
+            Blend::Multiply => {
+                let mut product = color;
+                for ((channel, top), bottom) in product.iter_mut().zip(color).zip(below).take(3) {
+                    *channel = (top as u32 * bottom as u32 / 255) as u8;
+                }
+                product
+            },
+        }
+    }
+}
+
+/// The color framebuffer of MEGA-CHIP mode.
+/// Sprites are drawn into a buffer that is shown with the next `00E0`,
+/// a pixel of a sprite is an index into the 255 colors the program loads, 0 is transparent.
+#[derive(Debug, Clone)]
+pub struct Framebuffer {
+    /// The colors loaded with `02nn`, 0 is transparent
+    pub palette: [Color; 256],
+    /// What is drawn, shown with the next `00E0`
+    drawn: Vec<Color>,
+    shown: Vec<Color>,
+    pub sprite_width: usize,
+    pub sprite_height: usize,
+    pub blend: Blend,
+    /// The brightness of the whole screen (`05nn`), 255 is fully visible
+    pub alpha: u8,
+    /// Drawing over a pixel of this color index is a collision (`09nn`)
+    pub collision_color: u8,
+}
+
+impl Framebuffer {
+    pub fn new() -> Framebuffer {
+        Framebuffer {
+            palette: [[0; 4]; 256],
+            drawn: vec![[0; 4]; WIDTH * HEIGHT],
+            shown: vec![[0; 4]; WIDTH * HEIGHT],
+            sprite_width: 0,
+            sprite_height: 0,
+            blend: Blend::default(),
+            alpha: 0xFF,
+            collision_color: 0,
+        }
+    }
+
+    /// `02nn`, colors 1 to nn as ARGB, 4 bytes each
+    pub fn load_palette(&mut self, argb: &[u8]) {
+        for (slot, color) in self.palette[1..].iter_mut().zip(argb.chunks_exact(4)) {
+            *slot = [color[1], color[2], color[3], color[0]];
+        }
+    }
+
+    /// Draw a sprite of `sprite_width` x `sprite_height` color indices, cut off at the edges.
+    /// `indices` are the color indices of the pixels that were drawn, for the collisions.
+    /// Returns true if a pixel of the collision color was drawn over.
+    pub fn draw(&mut self, indices: &mut [u8], x: usize, y: usize, sprite: &[u8]) -> bool {
+        let mut collision = false;
+        for (row, line) in sprite.chunks(self.sprite_width.max(1)).take(self.sprite_height).enumerate() {
+            for (column, &index) in line.iter().enumerate() {
+                let (px, py) = (x + column, y + row);
+                if index == 0 || px >= WIDTH || py >= HEIGHT {
+                    continue;
+                }
+                let pixel = px + py * WIDTH;
+                if indices[pixel] != 0 && indices[pixel] == self.collision_color {
+                    collision = true;
+                }
+                indices[pixel] = index;
+                self.drawn[pixel] = self.blend.apply(self.drawn[pixel], self.palette[index as usize]);
+            }
+        }
+        collision
+    }
+
+    /// Scroll the picture that is being drawn, see `Display::scroll`
+    pub fn scroll(&mut self, dx: isize, dy: isize) {
+        crate::scroll(&mut self.drawn, WIDTH, dx, dy, [0; 4]);
+    }
+
+    /// `00E0` shows what was drawn and starts a new picture
+    pub fn present(&mut self) {
+        std::mem::swap(&mut self.drawn, &mut self.shown);
+        self.drawn.fill([0; 4]);
+    }
+
+    /// Whether a shown pixel was drawn, the others are transparent
+    pub fn is_drawn(&self, x: usize, y: usize) -> bool {
+        self.shown[x + y * WIDTH][3] != 0
+    }
+
+    /// The color of a shown pixel, what is transparent shows the `background`
+    pub fn color(&self, x: usize, y: usize, background: Color) -> Color {
+        let color = self.shown[x + y * WIDTH];
+        if color[3] == 0 {
+            return background;
+        }
+        let alpha = self.alpha as u32;
+        let mut faded = color;
+        for ((channel, shown), background) in faded.iter_mut().zip(color).zip(background).take(3) {
+            *channel = ((shown as u32 * alpha + background as u32 * (255 - alpha)) / 255) as u8;
+        }
+        faded
+    }
+}
+
+impl Default for Framebuffer {
+    fn default() -> Framebuffer {
+        Framebuffer::new()
+    }
+}
+
+/// A sound played with `060n`: 8 bit unsigned samples
+#[derive(Debug, Clone)]
+pub struct Sample {
+    data: Vec<u8>,
+    /// Samples per second
+    rate: u32,
+    /// Where the playback is, in seconds
+    position: f64,
+    looping: bool,
+}
+
+impl Sample {
+    /// Read a sound from memory: the sample rate (2 bytes), the number of samples (3 bytes),
+    /// a byte that isn't used and then the samples. What is past the end of the memory is cut off.
+    pub fn read(memory: &[u8], address: usize, looping: bool) -> Sample {
+        let byte = |offset: usize| memory.get(address + offset).copied().unwrap_or(0) as usize;
+        let rate = (byte(0) << 8 | byte(1)) as u32;
+        let length = byte(2) << 16 | byte(3) << 8 | byte(4);
+        let start = (address + 6).min(memory.len());
+        let end = (start + length).min(memory.len());
+        Sample {
+            data: memory[start..end].to_vec(),
+            rate: rate.max(1),
+            position: 0.0,
+            looping,
+        }
+    }
+
+    /// The sample `seconds` after the current position (before it if negative),
+    /// silence past the end
+    pub fn value_at(&self, seconds: f64) -> u8 {
+        if self.data.is_empty() {
+            return 0x80;
+        }
+        let index = ((self.position + seconds).max(0.0) * self.rate as f64) as usize;
+        if self.looping {
+            self.data[index % self.data.len()]
+        } else {
+            self.data.get(index).copied().unwrap_or(0x80)
+        }
+    }
+
+    /// Play on for `seconds`, returns false once the sound is over
+    pub fn advance(&mut self, seconds: f64) -> bool {
+        self.position += seconds;
+        self.looping || (self.position * self.rate as f64) < self.data.len() as f64
+    }
+}
@@ -0,0 +1,139 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::{Config, QuirksConfig, Settings};
+use crate::font::FontStyle;
+
+/// The options Octo keeps for a program, the way it writes them in its JSON.
+/// Only the ones wrack knows about are read and written.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct OctoOptions {
+    /// Instructions per frame
+    pub tickrate: Option<u32>,
+    pub background_color: Option<String>,
+    pub fill_color: Option<String>,
+    pub fill_color2: Option<String>,
+    pub blend_color: Option<String>,
+    pub shift_quirks: Option<bool>,
+    pub load_store_quirks: Option<bool>,
+    pub logic_quirks: Option<bool>,
+    pub jump_quirks: Option<bool>,
+    pub clip_quirks: Option<bool>,
+    #[serde(rename = "vBlankQuirks")]
+    pub vblank_quirks: Option<bool>,
+    pub vf_order_quirks: Option<bool>,
+    /// Degrees the picture is turned clockwise
+    pub screen_rotation: Option<u16>,
+    pub font_style: Option<String>,
+}
+
+impl OctoOptions {
+    /// Read an options file
+    pub fn load(path: &Path) -> Result<OctoOptions, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| format!("could not read {}: {}", path.display(), e))?;
+        serde_json::from_str(&text).map_err(|e| format!("could not parse {}: {}", path.display(), e))
+    }
+
+    /// The settings as Octo would have them
+    pub fn from_settings(settings: &Settings) -> OctoOptions {
+        let [background, fill, fill2, blend] = settings.palette.colors.map(|[r, g, b, _]| Some(format!("#{:02X}{:02X}{:02X}", r, g, b)));
+        OctoOptions {
+            tickrate: Some(settings.ipf),
+            background_color: background,
+            fill_color: fill,
+            fill_color2: fill2,
+            blend_color: blend,
+            shift_quirks: Some(settings.quirks.shift),
+            load_store_quirks: Some(settings.quirks.load_store),
+            logic_quirks: Some(settings.quirks.vf_reset),
+            jump_quirks: Some(settings.quirks.jump),
+            clip_quirks: Some(settings.quirks.clip),
+            vblank_quirks: Some(settings.quirks.display_wait),
+            vf_order_quirks: Some(settings.quirks.vf_order),
+            screen_rotation: Some(settings.rotation.degrees()),
+            // Octo only knows its own fonts
+            font_style: FontStyle::ALL
+                .into_iter()
+                .find(|style| style.font() == settings.font)
+                .map(|style| style.name().to_string()),
+        }
+    }
+
+    pub fn to_json(&self) -> Result<String, String> {
+        serde_json::to_string_pretty(self).map_err(|e| e.to_string())
+    }
+
+    /// The options as a layer of the config
+    pub fn to_config(&self) -> Config {
+        // The palette is filled from the background on, as far as the colors are given
+        let colors: Vec<String> = [&self.background_color, &self.fill_color, &self.fill_color2, &self.blend_color]
+            .into_iter()
+            .map_while(|color| color.clone())
+            .collect();
+        Config {
+            ipf: self.tickrate,
+            // Only the built in fonts, a cartridge can't point at a file
+            font: self.font_style.clone().filter(|name| FontStyle::from_name(name).is_some()),
+            colors: (!colors.is_empty()).then_some(colors),
+            rotation: self.screen_rotation,
+            // Octo has every quirk off unless the options turn it on, the programs
+            // it compiles count on that (its comparisons need VF written last)
+            quirks: QuirksConfig {
+                shift: Some(self.shift_quirks.unwrap_or(false)),
+                load_store: Some(self.load_store_quirks.unwrap_or(false)),
+                vf_reset: Some(self.logic_quirks.unwrap_or(false)),
+                jump: Some(self.jump_quirks.unwrap_or(false)),
+                clip: Some(self.clip_quirks.unwrap_or(false)),
+                display_wait: Some(self.vblank_quirks.unwrap_or(false)),
+                vf_order: Some(self.vf_order_quirks.unwrap_or(false)),
+            },
+            ..Config::default()
+        }
+    }
+}
+
+/// What an Octo cartridge holds
+#[derive(Debug, Clone, Deserialize)]
+pub struct Cartridge {
+    /// The Octo source of the program
+    pub program: String,
+    #[serde(default)]
+    pub options: OctoOptions,
+}
+
+/// The start of every GIF, which is what Octo cartridges are
+pub const GIF_MAGIC: &[u8] = b"GIF8";
+
+/// Get the program out of a cartridge.
+/// The data is hidden in the pixels of the image: the lower nibble of the color index
+/// of every pixel is half a byte, the high nibble first. The first 4 bytes are the length
+/// of the JSON that follows, which has the program and its options.
+pub fn read_cartridge(gif: &[u8]) -> io::Result<Cartridge> {
+    let mut options = gif::DecodeOptions::new();
+    options.set_color_output(gif::ColorOutput::Indexed);
+    let mut decoder = options.read_info(gif).map_err(invalid)?;
+
+    let mut nibbles = Vec::new();
+    while let Some(frame) = decoder.read_next_frame().map_err(invalid)? {
+        nibbles.extend(frame.buffer.iter().map(|index| index & 0xF));
+    }
+    let bytes: Vec<u8> = nibbles.chunks_exact(2).map(|pair| pair[0] << 4 | pair[1]).collect();
+
+    if bytes.len() < 4 {
+        return Err(invalid("the image is too small to be a cartridge"));
+    }
+    let length = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize;
+    let json = bytes
+        .get(4..4 + length)
+        .ok_or_else(|| invalid("the image is not an Octo cartridge"))?;
+    serde_json::from_slice(json).map_err(|e| invalid(format!("the cartridge is broken: {}", e)))
+}
+
+fn invalid<E: Into<Box<dyn std::error::Error + Send + Sync>>>(error: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error)
+}
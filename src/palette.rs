@@ -0,0 +1,113 @@
+/// An RGBA color, the way `pixels` wants it
+pub type Color = [u8; 4];
+
+/// The colors used to present the display.
+/// A pixel is looked up by the value it has in the display memory,
+/// one bit for each bitplane:
+/// 0 - background
+/// 1 - foreground, the only one used by plain Chip-8
+/// 2 - second bitplane (XO-CHIP)
+/// 3 - pixel set in both bitplanes (XO-CHIP)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Palette {
+    pub colors: [Color; 4],
+}
+
+impl Palette {
+    /// The color to use for the pixel value `planes`
+    pub fn color(&self, planes: u8) -> Color {
+        self.colors[(planes & 0x3) as usize]
+    }
+
+    pub fn with_background(mut self, color: Color) -> Palette {
+        self.colors[0] = color;
+        self
+    }
+
+    pub fn with_foreground(mut self, color: Color) -> Palette {
+        self.colors[1] = color;
+        self
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Palette {
+        Theme::Classic.palette()
+    }
+}
+
+/// The built in color themes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Theme {
+    /// White on black
+    Classic,
+    /// Amber monochrome monitor
+    Amber,
+    /// Green phosphor monochrome monitor
+    GreenPhosphor,
+    /// Greenish LCD of a handheld
+    Lcd,
+    /// The default colors of the Octo IDE
+    Octo,
+}
+
+impl Theme {
+    pub const ALL: [Theme; 5] = [
+        Theme::Classic,
+        Theme::Amber,
+        Theme::GreenPhosphor,
+        Theme::Lcd,
+        Theme::Octo,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Theme::Classic => "classic",
+            Theme::Amber => "amber",
+            Theme::GreenPhosphor => "green",
+            Theme::Lcd => "lcd",
+            Theme::Octo => "octo",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Theme> {
+        Theme::ALL.iter().copied().find(|theme| theme.name().eq_ignore_ascii_case(name))
+    }
+
+    /// The next theme, for cycling through them with a hotkey
+    pub fn next(&self) -> Theme {
+        let index = Theme::ALL.iter().position(|theme| theme == self).unwrap_or(0);
+        Theme::ALL[(index + 1) % Theme::ALL.len()]
+    }
+
+    pub fn palette(&self) -> Palette {
+        let colors = match self {
+            Theme::Classic => [0x000000, 0xFFFFFF, 0xAAAAAA, 0x555555],
+            Theme::Amber => [0x1A0F00, 0xFFB000, 0xB37B00, 0x664600],
+            Theme::GreenPhosphor => [0x0B1A0B, 0x33FF33, 0x22AA22, 0x115511],
+            Theme::Lcd => [0x9BBC0F, 0x0F380F, 0x306230, 0x8BAC0F],
+            Theme::Octo => [0x996600, 0xFFCC00, 0xFF6600, 0x662200],
+        };
+        Palette { colors: colors.map(rgb) }
+    }
+}
+
+/// An opaque color from a 0xRRGGBB value
+fn rgb(value: u32) -> Color {
+    let [_, r, g, b] = value.to_be_bytes();
+    [r, g, b, 0xFF]
+}
+
+/// Parse a color written as `RRGGBB` or `RRGGBBAA`, with an optional leading `#`
+pub fn parse_color(text: &str) -> Option<Color> {
+    let hex = text.strip_prefix('#').unwrap_or(text);
+    if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    let value = u32::from_str_radix(hex, 16).ok()?;
+    match hex.len() {
+        6 => Some(rgb(value)),
+        8 => Some(value.to_be_bytes()),
+        _ => None,
+    }
+}
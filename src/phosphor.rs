@@ -0,0 +1,54 @@
+use crate::palette::Color;
+
+/// The decay used when phosphor persistence is toggled on without a value
+pub const DEFAULT_DECAY: f32 = 0.6;
+
+/// Simulates the afterglow of a CRT to hide the flicker of the XOR drawing.
+/// Pixels light up at once, but fade out over a couple of frames when erased.
+/// This is only applied to the presented frame, the display of the chip is left alone.
+pub struct Phosphor {
+    // How much of the old intensity is kept each frame, between 0 and 1
+    decay: f32,
+    // The presented color of every pixel, RGBA
+    glow: Vec<f32>,
+}
+
+impl Phosphor {
+    pub fn new(decay: f32) -> Phosphor {
+        Phosphor {
+            decay: decay.clamp(0.0, 1.0),
+            glow: Vec::new(),
+        }
+    }
+
+    pub fn decay(&self) -> f32 {
+        self.decay
+    }
+
+    /// Blend the color of one pixel with what was presented before.
+    /// `lit` tells if the pixel is set in the display,
+    /// lit pixels show their color right away, unlit ones fade towards it.
+    pub fn blend(&mut self, index: usize, color: Color, lit: bool) -> Color {
+        let start = index * 4;
+        if self.glow.len() < start + 4 {
+            // Nothing presented here yet, start without an afterglow
+            self.glow.resize(start + 4, 0.0);
+            for (glow, channel) in self.glow[start..].iter_mut().zip(color) {
+                *glow = channel as f32;
+            }
+        }
+        let glow = &mut self.glow[start..start + 4];
+
+        let mut blended = color;
+        for ((glow, channel), out) in glow.iter_mut().zip(color).zip(blended.iter_mut()) {
+            let target = channel as f32;
+            *glow = if lit {
+                target
+            } else {
+                target + (*glow - target) * self.decay
+            };
+            *out = glow.round() as u8;
+        }
+        blended
+    }
+}
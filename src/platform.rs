@@ -0,0 +1,207 @@
+use crate::font::FontStyle;
+
+/// The behaviour of some instructions differs between the interpreters,
+/// games are written against one of them and break on the others.
+/// See <https://chip-8.github.io/extensions/> and [4] in the README.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Quirks {
+    /// `8xy6`/`8xyE` shift Vx in place instead of putting the shifted Vy into Vx
+    pub shift: bool,
+    /// `Fx55`/`Fx65` leave I alone instead of incrementing it past the last register
+    pub load_store: bool,
+    /// `8xy1`/`8xy2`/`8xy3` reset VF to 0
+    pub vf_reset: bool,
+    /// `Bnnn` jumps to xnn + Vx instead of nnn + V0
+    pub jump: bool,
+    /// Sprites are clipped at the edges of the screen instead of wrapping around
+    pub clip: bool,
+    /// `Dxyn` waits for the next frame (the vertical blank), so there are at most 60 sprites a second
+    pub display_wait: bool,
+    /// `8xy4`/`8xy5`/`8xy6`/`8xy7`/`8xyE` write VF before Vx, so `8Fyn` keeps the result instead of the flag
+    pub vf_order: bool,
+}
+
+/// A quirk to turn on or off from the command line
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Quirk {
+    Shift,
+    LoadStore,
+    VfReset,
+    Jump,
+    Clip,
+    DisplayWait,
+    VfOrder,
+}
+
+impl Default for Quirks {
+    fn default() -> Quirks {
+        Platform::default().quirks()
+    }
+}
+
+impl Quirks {
+    pub fn set(&mut self, quirk: Quirk, on: bool) {
+        match quirk {
+            Quirk::Shift => self.shift = on,
+            Quirk::LoadStore => self.load_store = on,
+            Quirk::VfReset => self.vf_reset = on,
+            Quirk::Jump => self.jump = on,
+            Quirk::Clip => self.clip = on,
+            Quirk::DisplayWait => self.display_wait = on,
+            Quirk::VfOrder => self.vf_order = on,
+        }
+    }
+}
+
+/// The interpreters a program can be written for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Platform {
+    /// The way most modern interpreters (and wrack since the beginning) behave
+    #[default]
+    Modern,
+    /// The original CHIP-8 interpreter of the COSMAC VIP
+    Vip,
+    /// The ETI-660, which runs CHIP-8 like the VIP but loads programs at 0x600
+    #[value(name = "eti660")]
+    Eti660,
+    /// The VIP with the hi-res interpreter: a 64x64 display, the program starts at 0x2C0
+    /// after the part of the interpreter that comes with it
+    Hires,
+    /// CHIP-8X of RCA, the VIP with the VP-590 color board and a second keypad
+    #[value(name = "chip8x")]
+    Chip8x,
+    /// MEGA-CHIP8, SUPER-CHIP with a 256x192 mode of 256 colors, 16M of memory and sampled sound
+    Megachip,
+}
+
+/// Where things are in the memory of an interpreter
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Layout {
+    /// Bytes of memory
+    pub memory_size: usize,
+    /// Where programs are loaded and started
+    pub load_address: u16,
+    pub font_address: u16,
+    /// Ranges of addresses (inclusive) the interpreter keeps for itself, programs can't be loaded there
+    pub reserved: Vec<(u16, u16)>,
+}
+
+impl Platform {
+    /// The digits the interpreter comes with
+    pub fn font(&self) -> FontStyle {
+        match self {
+            Platform::Modern => FontStyle::Octo,
+            Platform::Vip => FontStyle::Vip,
+            Platform::Eti660 => FontStyle::Eti660,
+            Platform::Hires | Platform::Chip8x => FontStyle::Vip,
+            Platform::Megachip => FontStyle::Schip,
+        }
+    }
+
+    /// How many subroutine calls the interpreter has room for
+    pub fn stack_depth(&self) -> usize {
+        match self {
+            Platform::Modern | Platform::Eti660 | Platform::Megachip => 16,
+            Platform::Vip | Platform::Hires | Platform::Chip8x => 12,
+        }
+    }
+
+    /// How many RPL user flags `Fx75`/`Fx85` have, the interpreters of the VIP have none
+    pub fn flag_count(&self) -> usize {
+        match self {
+            // Like XO-CHIP
+            Platform::Modern => 16,
+            Platform::Megachip => 8,
+            Platform::Vip | Platform::Eti660 | Platform::Hires | Platform::Chip8x => 0,
+        }
+    }
+
+    /// Width and height of the display
+    pub fn display_size(&self) -> (usize, usize) {
+        match self {
+            Platform::Hires => (64, 64),
+            Platform::Megachip => (crate::megachip::WIDTH, crate::megachip::HEIGHT),
+            _ => (64, 32),
+        }
+    }
+
+    pub fn layout(&self) -> Layout {
+        match self {
+            // The font goes where the interpreter would be
+            Platform::Modern => Layout {
+                memory_size: 4096,
+                load_address: 0x200,
+                font_address: 0x000,
+                reserved: vec![(0x000, 0x1FF)],
+            },
+            // The interpreter is at the bottom, its variables, the stack and the display at the top
+            Platform::Vip => Layout {
+                memory_size: 4096,
+                load_address: 0x200,
+                font_address: 0x000,
+                reserved: vec![(0x000, 0x1FF), (0xEA0, 0xFFF)],
+            },
+            Platform::Eti660 => Layout {
+                memory_size: 4096,
+                load_address: 0x600,
+                font_address: 0x000,
+                reserved: vec![(0x000, 0x5FF)],
+            },
+            // Programs start with a jump over the 1802 code that extends the interpreter
+            Platform::Hires => Layout {
+                memory_size: 4096,
+                load_address: 0x200,
+                font_address: 0x000,
+                reserved: vec![(0x000, 0x1FF)],
+            },
+            // The interpreter is a page longer
+            Platform::Chip8x => Layout {
+                memory_size: 4096,
+                load_address: 0x300,
+                font_address: 0x000,
+                reserved: vec![(0x000, 0x2FF)],
+            },
+            // I has 24 bits, the sprites and the sounds are big
+            Platform::Megachip => Layout {
+                memory_size: 0x1000000,
+                load_address: 0x200,
+                font_address: 0x000,
+                reserved: vec![(0x000, 0x1FF)],
+            },
+        }
+    }
+
+    pub fn quirks(&self) -> Quirks {
+        match self {
+            Platform::Modern => Quirks {
+                shift: true,
+                load_store: true,
+                vf_reset: false,
+                jump: false,
+                clip: false,
+                display_wait: false,
+                // What wrack always did
+                vf_order: true,
+            },
+            Platform::Megachip => Quirks {
+                shift: true,
+                load_store: true,
+                vf_reset: false,
+                jump: true,
+                clip: true,
+                display_wait: false,
+                vf_order: false,
+            },
+            Platform::Vip | Platform::Eti660 | Platform::Hires | Platform::Chip8x => Quirks {
+                shift: false,
+                load_store: false,
+                vf_reset: true,
+                jump: false,
+                clip: true,
+                display_wait: true,
+                vf_order: false,
+            },
+        }
+    }
+}
@@ -0,0 +1,137 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+/// The seed of a chip that isn't seeded otherwise, so it does the same every time
+pub const DEFAULT_SEED: u64 = 0;
+
+/// Where `Cxkk` gets its random numbers from
+pub trait Random {
+    /// The next random byte
+    fn byte(&mut self) -> u8;
+
+    /// Start over with a seed, the same seed gives the same numbers
+    fn seed(&mut self, seed: u64);
+}
+
+/// The random number generators to pick from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Generator {
+    /// Good random numbers, every byte is as likely as the others
+    #[default]
+    Standard,
+    /// The routine of the CHIP-8 interpreter of the COSMAC VIP
+    Vip,
+}
+
+/// Random numbers from `rand`
+pub struct Standard {
+    rng: StdRng,
+}
+
+impl Standard {
+    pub fn new(seed: u64) -> Standard {
+        Standard { rng: StdRng::seed_from_u64(seed) }
+    }
+}
+
+impl Default for Standard {
+    fn default() -> Standard {
+        Standard::new(DEFAULT_SEED)
+    }
+}
+
+impl Random for Standard {
+    fn byte(&mut self) -> u8 {
+        self.rng.gen()
+    }
+
+    fn seed(&mut self, seed: u64) {
+        self.rng = StdRng::seed_from_u64(seed);
+    }
+}
+
+/// The way the interpreter of the VIP does it: R9 is counted up, then the byte of the
+/// interpreter at 0x100 plus the low byte of R9 is added to the high byte of R9.
+/// That sum is the random number and stays in R9 for the next one.
+/// The numbers are only as good as the bytes of the interpreter they come from,
+/// which is why it needs the interpreter itself.
+pub struct Vip {
+    r9: u16,
+    /// The second page of the interpreter
+    page: [u8; 256],
+}
+
+impl Vip {
+    /// `interpreter` is the CHIP-8 interpreter of the VIP, the first 512 bytes of its memory
+    pub fn new(interpreter: &[u8]) -> Result<Vip, String> {
+        let page = interpreter
+            .get(0x100..0x200)
+            .ok_or_else(|| format!("the interpreter of the VIP has 512 bytes, not {}", interpreter.len()))?;
+        Ok(Vip {
+            r9: DEFAULT_SEED as u16,
+            page: page.try_into().unwrap(),
+        })
+    }
+}
+
+impl Random for Vip {
+    fn byte(&mut self) -> u8 {
+        self.r9 = self.r9.wrapping_add(1);
+        let [high, low] = self.r9.to_be_bytes();
+        let byte = self.page[low as usize].wrapping_add(high);
+        self.r9 = u16::from_be_bytes([byte, low]);
+        byte
+    }
+
+    /// The VIP has nothing but R9 to seed
+    fn seed(&mut self, seed: u64) {
+        self.r9 = seed as u16;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_seed_same_numbers() {
+        let (mut first, mut second) = (Standard::new(42), Standard::new(42));
+        let numbers: Vec<u8> = (0..64).map(|_| first.byte()).collect();
+        assert_eq!(numbers, (0..64).map(|_| second.byte()).collect::<Vec<u8>>());
+
+        // Seeding again starts over
+        first.seed(42);
+        assert_eq!(numbers, (0..64).map(|_| first.byte()).collect::<Vec<u8>>());
+    }
+
+    #[test]
+    fn every_byte_comes_up() {
+        let mut random = Standard::default();
+        let mut seen = [false; 256];
+        for _ in 0..100_000 {
+            seen[random.byte() as usize] = true;
+        }
+        assert!(seen.iter().all(|&seen| seen), "never got {:?}", seen.iter().position(|&seen| !seen));
+    }
+
+    #[test]
+    fn vip_adds_the_page_to_r9() {
+        // With the byte at 0x100 + n being n, every number is the one before plus the count
+        let mut interpreter = vec![0; 0x100];
+        interpreter.extend(0..=255);
+        let mut random = Vip::new(&interpreter).unwrap();
+        let numbers: Vec<u8> = (0..5).map(|_| random.byte()).collect();
+        assert_eq!(numbers, [1, 3, 6, 10, 15]);
+
+        // R9 0x10FF: the low byte is counted up to 0x00, the high byte to 0x11
+        random.seed(0x10FF);
+        assert_eq!(random.byte(), 0x11);
+    }
+
+    #[test]
+    fn vip_needs_the_whole_interpreter() {
+        assert!(Vip::new(&[0; 0x100]).is_err());
+    }
+}
@@ -0,0 +1,372 @@
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::io::{BufWriter, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use gif::{Encoder, Frame, Repeat};
+
+use crate::megachip::Sample;
+
+/// Every recorded pixel is blown up to a block of this size,
+/// a 64x32 clip is a bit too small to share otherwise
+const SCALE: usize = 4;
+
+/// The Y4M stream has a fixed frame rate,
+/// the same rate the timers of the chip are running at
+const FPS: u64 = 60;
+
+/// Sample rate of the recorded sound in Hz
+const SAMPLE_RATE: u32 = 22050;
+
+/// Frequency of the beep the sound timer produces in Hz
+pub const DEFAULT_TONE: u32 = 440;
+
+/// Loudness of the beep, between 0 and 1
+pub const DEFAULT_VOLUME: f32 = 0.5;
+
+/// How the sound timer sounds
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Audio {
+    /// Frequency in Hz
+    pub tone: u32,
+    /// Between 0 and 1
+    pub volume: f32,
+}
+
+impl Default for Audio {
+    fn default() -> Audio {
+        Audio { tone: DEFAULT_TONE, volume: DEFAULT_VOLUME }
+    }
+}
+
+/// What a machine plays right now
+#[derive(Debug, Clone, Copy)]
+pub enum Sound<'a> {
+    Silent,
+    /// The beep of the sound timer
+    Beep,
+    /// A sound of MEGA-CHIP
+    Sample(&'a Sample),
+}
+
+/// The video formats the recorder can write
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VideoFormat {
+    /// Animated GIF, looping forever
+    Gif,
+    /// Raw YUV4MPEG2 stream, which can be fed into ffmpeg & co.
+    Y4m,
+}
+
+impl VideoFormat {
+    /// Guess the format from the extension of the file
+    pub fn from_path(path: &Path) -> Option<VideoFormat> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "gif" => Some(VideoFormat::Gif),
+            "y4m" => Some(VideoFormat::Y4m),
+            _ => None,
+        }
+    }
+
+    fn extension(&self) -> &'static str {
+        match self {
+            VideoFormat::Gif => "gif",
+            VideoFormat::Y4m => "y4m",
+        }
+    }
+}
+
+enum Video {
+    Gif {
+        encoder: Encoder<BufWriter<File>>,
+        // The last captured frame and the time (in centiseconds) it was shown first.
+        // It is only written once we know for how long it stayed on screen.
+        pending: Option<(Vec<u8>, u64)>,
+    },
+    Y4m {
+        writer: BufWriter<File>,
+        // The last captured frame, which is repeated until a new one arrives
+        last: Vec<u8>,
+        frames: u64,
+    },
+}
+
+/// Captures the rendered frames into a video file
+/// and the sound timer into a WAV file next to it
+pub struct Recorder {
+    video: Video,
+    audio: WavWriter,
+    width: usize,
+    height: usize,
+    started: Instant,
+    path: PathBuf,
+}
+
+impl Recorder {
+    /// Start a recording of a `width` x `height` RGBA frame into `path`.
+    /// The sound is written into the same path with a `.wav` extension.
+    pub fn start(path: &Path, format: VideoFormat, width: usize, height: usize, audio: Audio) -> io::Result<Recorder> {
+        let out_width = width * SCALE;
+        let out_height = height * SCALE;
+        let file = BufWriter::new(File::create(path)?);
+        let video = match format {
+            VideoFormat::Gif => {
+                let mut encoder = Encoder::new(file, out_width as u16, out_height as u16, &[])
+                    .map_err(to_io_error)?;
+                encoder.set_repeat(Repeat::Infinite).map_err(to_io_error)?;
+                Video::Gif { encoder, pending: None }
+            },
+            VideoFormat::Y4m => {
+                let mut writer = file;
+                writeln!(writer, "YUV4MPEG2 W{} H{} F{}:1 Ip A1:1 C444", out_width, out_height, FPS)?;
+                Video::Y4m { writer, last: vec![0; width * height * 4], frames: 0 }
+            },
+        };
+        let audio = WavWriter::create(&path.with_extension("wav"), audio)?;
+
+        Ok(Recorder {
+            video,
+            audio,
+            width,
+            height,
+            started: Instant::now(),
+            path: path.to_path_buf(),
+        })
+    }
+
+    /// Start a recording into a file named after the current time in the working directory
+    pub fn start_timestamped(format: VideoFormat, width: usize, height: usize, audio: Audio) -> io::Result<Recorder> {
+        let secs = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        let path = PathBuf::from(format!("wrack-{}.{}", secs, format.extension()));
+        Recorder::start(&path, format, width, height, audio)
+    }
+
+    /// The file the video is written to
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Capture a frame, `frame` being the RGBA data that is presented in the window
+    /// and `size` its width and height. A frame in another resolution than the recording
+    /// (after the ROM was switched) is scaled to fit.
+    /// `sound` is what plays right now.
+    pub fn capture(&mut self, frame: &[u8], size: (usize, usize), sound: Sound) -> io::Result<()> {
+        let elapsed = self.started.elapsed();
+        let (width, height) = (self.width, self.height);
+        let resized;
+        let frame = if size == (width, height) {
+            frame
+        } else {
+            resized = resize(frame, size, (width, height));
+            &resized
+        };
+
+        match &mut self.video {
+            Video::Gif { encoder, pending } => {
+                let now = elapsed.as_millis() as u64 / 10;
+                match pending {
+                    // Same picture as before, it just stays on screen a bit longer
+                    Some((last, _)) if last.as_slice() == frame => (),
+                    // Still the same centisecond, the new frame replaces the old one
+                    Some((last, shown)) if *shown == now => last.copy_from_slice(frame),
+                    _ => {
+                        if let Some((last, shown)) = pending.take() {
+                            write_gif_frame(encoder, &last, width, height, now - shown)?;
+                        }
+                        *pending = Some((frame.to_vec(), now));
+                    },
+                }
+            },
+            Video::Y4m { writer, last, frames } => {
+                // Repeat the previous frame for every tick that passed without a capture
+                let due = elapsed.as_millis() as u64 * FPS / 1000;
+                while *frames < due {
+                    write_y4m_frame(writer, last, width, height)?;
+                    *frames += 1;
+                }
+                last.copy_from_slice(frame);
+            },
+        }
+
+        let due = elapsed.as_micros() as u64 * SAMPLE_RATE as u64 / 1_000_000;
+        self.audio.fill_until(due, sound)
+    }
+
+    /// Stop the recording and flush everything to disk
+    pub fn finish(mut self) -> io::Result<()> {
+        let elapsed = self.started.elapsed();
+        let (width, height) = (self.width, self.height);
+
+        match &mut self.video {
+            Video::Gif { encoder, pending } => {
+                if let Some((last, shown)) = pending.take() {
+                    let now = elapsed.as_millis() as u64 / 10;
+                    write_gif_frame(encoder, &last, width, height, now.saturating_sub(shown).max(1))?;
+                }
+                encoder.get_mut().flush()?;
+            },
+            Video::Y4m { writer, last, .. } => {
+                write_y4m_frame(writer, last, width, height)?;
+                writer.flush()?;
+            },
+        }
+
+        self.audio.finish()
+    }
+}
+
+fn to_io_error(err: gif::EncodingError) -> io::Error {
+    io::Error::other(err)
+}
+
+/// Scale an RGBA frame to another resolution, nearest neighbour
+fn resize(frame: &[u8], (width, height): (usize, usize), (to_width, to_height): (usize, usize)) -> Vec<u8> {
+    let mut resized = Vec::with_capacity(to_width * to_height * 4);
+    for y in 0..to_height {
+        for x in 0..to_width {
+            let start = (x * width / to_width + y * height / to_height * width) * 4;
+            resized.extend_from_slice(&frame[start..start + 4]);
+        }
+    }
+    resized
+}
+
+/// Blow up an RGBA frame by `SCALE` in both directions
+fn scale(frame: &[u8], width: usize, height: usize) -> Vec<u8> {
+    let mut scaled = Vec::with_capacity(frame.len() * SCALE * SCALE);
+    for y in 0..height {
+        let row = &frame[y * width * 4..(y + 1) * width * 4];
+        for _ in 0..SCALE {
+            for pixel in row.chunks_exact(4) {
+                for _ in 0..SCALE {
+                    scaled.extend_from_slice(pixel);
+                }
+            }
+        }
+    }
+    scaled
+}
+
+fn write_gif_frame(encoder: &mut Encoder<BufWriter<File>>, frame: &[u8], width: usize, height: usize, delay: u64) -> io::Result<()> {
+    let mut rgba = scale(frame, width, height);
+    let (w, h) = ((width * SCALE) as u16, (height * SCALE) as u16);
+
+    // Most of the time there are only a handful of colors on screen,
+    // so an exact palette can be used instead of quantizing the frame
+    let mut palette: Vec<[u8; 3]> = Vec::new();
+    let mut indices = Vec::with_capacity(rgba.len() / 4);
+    for pixel in rgba.chunks_exact(4) {
+        let color = [pixel[0], pixel[1], pixel[2]];
+        let index = match palette.iter().position(|c| *c == color) {
+            Some(index) => index,
+            None => {
+                palette.push(color);
+                palette.len() - 1
+            },
+        };
+        if palette.len() > 256 {
+            break;
+        }
+        indices.push(index as u8);
+    }
+
+    let mut gif_frame = if palette.len() <= 256 {
+        Frame::from_palette_pixels(w, h, &indices, &palette.concat(), None)
+    } else {
+        for pixel in rgba.chunks_exact_mut(4) {
+            pixel[3] = 0xFF;
+        }
+        Frame::from_rgba_speed(w, h, &mut rgba, 10)
+    };
+    gif_frame.delay = delay.min(u16::MAX as u64) as u16;
+    encoder.write_frame(&gif_frame).map_err(to_io_error)
+}
+
+fn write_y4m_frame(writer: &mut BufWriter<File>, frame: &[u8], width: usize, height: usize) -> io::Result<()> {
+    let rgba = scale(frame, width, height);
+    let size = rgba.len() / 4;
+    let mut planes = vec![0u8; size * 3];
+
+    // BT.601 studio swing, which is what players assume for Y4M
+    for (i, pixel) in rgba.chunks_exact(4).enumerate() {
+        let (r, g, b) = (pixel[0] as f32, pixel[1] as f32, pixel[2] as f32);
+        planes[i] = (16.0 + 0.257 * r + 0.504 * g + 0.098 * b).round() as u8;
+        planes[size + i] = (128.0 - 0.148 * r - 0.291 * g + 0.439 * b).round() as u8;
+        planes[size * 2 + i] = (128.0 + 0.439 * r - 0.368 * g - 0.071 * b).round() as u8;
+    }
+
+    writer.write_all(b"FRAME\n")?;
+    writer.write_all(&planes)
+}
+
+/// Minimal 8 bit mono PCM WAV writer
+struct WavWriter {
+    writer: BufWriter<File>,
+    samples: u64,
+    audio: Audio,
+}
+
+impl WavWriter {
+    fn create(path: &Path, audio: Audio) -> io::Result<WavWriter> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        // The sizes are patched in once the recording is finished
+        writer.write_all(&WavWriter::header(0))?;
+        Ok(WavWriter { writer, samples: 0, audio })
+    }
+
+    fn header(samples: u32) -> [u8; 44] {
+        let mut header = [0; 44];
+        header[0..4].copy_from_slice(b"RIFF");
+        header[4..8].copy_from_slice(&(36 + samples).to_le_bytes());
+        header[8..12].copy_from_slice(b"WAVE");
+        header[12..16].copy_from_slice(b"fmt ");
+        header[16..20].copy_from_slice(&16u32.to_le_bytes());
+        // PCM, 1 channel
+        header[20..22].copy_from_slice(&1u16.to_le_bytes());
+        header[22..24].copy_from_slice(&1u16.to_le_bytes());
+        header[24..28].copy_from_slice(&SAMPLE_RATE.to_le_bytes());
+        // byte rate, block align and bits per sample
+        header[28..32].copy_from_slice(&SAMPLE_RATE.to_le_bytes());
+        header[32..34].copy_from_slice(&1u16.to_le_bytes());
+        header[34..36].copy_from_slice(&8u16.to_le_bytes());
+        header[36..40].copy_from_slice(b"data");
+        header[40..44].copy_from_slice(&samples.to_le_bytes());
+        header
+    }
+
+    /// Write samples up to sample number `due`: a square wave for the beep,
+    /// the end of what a sound played up to now, or silence
+    fn fill_until(&mut self, due: u64, sound: Sound) -> io::Result<()> {
+        let half_period = (SAMPLE_RATE / self.audio.tone.max(1) / 2).max(1) as u64;
+        let volume = self.audio.volume.clamp(0.0, 1.0);
+        let amplitude = (volume * 127.0) as u8;
+        while self.samples < due {
+            let sample = match sound {
+                Sound::Silent => 0x80,
+                Sound::Beep if (self.samples / half_period) & 1 == 0 => 0x80 + amplitude,
+                Sound::Beep => 0x80 - amplitude,
+                Sound::Sample(sample) => {
+                    let ago = (due - self.samples) as f64 / SAMPLE_RATE as f64;
+                    let value = sample.value_at(-ago) as f32 - 128.0;
+                    (128.0 + value * volume) as u8
+                },
+            };
+            self.writer.write_all(&[sample])?;
+            self.samples += 1;
+        }
+        Ok(())
+    }
+
+    fn finish(mut self) -> io::Result<()> {
+        let header = WavWriter::header(self.samples.min(u32::MAX as u64 - 36) as u32);
+        self.writer.seek(SeekFrom::Start(0))?;
+        self.writer.write_all(&header)?;
+        self.writer.flush()
+    }
+}
@@ -0,0 +1,169 @@
+use std::fs;
+use std::io::{self, Cursor, IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
+
+use flate2::read::GzDecoder;
+use sha1::{Digest, Sha1};
+use zip::ZipArchive;
+
+use crate::assembler;
+use crate::config::Config;
+use crate::octo;
+
+/// The extensions of ROMs looked for in archives
+const ROM_EXTENSIONS: [&str; 4] = ["ch8", "sc8", "xo8", "gif"];
+
+const GZIP_MAGIC: &[u8] = &[0x1F, 0x8B];
+const ZIP_MAGIC: &[u8] = b"PK\x03\x04";
+
+/// A ROM and the settings that came with it
+#[derive(Debug, Clone, Default)]
+pub struct Rom {
+    pub program: Vec<u8>,
+    /// The options of an Octo cartridge, empty for everything else
+    pub options: Config,
+}
+
+/// The SHA-1 of a ROM as lowercase hex.
+/// It identifies the ROM in the config and in the ROM database.
+pub fn hash(rom: &[u8]) -> String {
+    Sha1::digest(rom)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Read a ROM, which can be
+/// * a plain file
+/// * `-` for standard input
+/// * gzip compressed
+/// * in a zip archive, either as `archive.zip#name` or on its own in `archive.zip`.
+///   With more than one ROM in the archive the user is asked which one to use.
+/// * an Octo cartridge, a GIF with the source and options of the program in it
+pub fn read(path: &Path) -> io::Result<Rom> {
+    if path == Path::new("-") {
+        let mut data = Vec::new();
+        io::stdin().read_to_end(&mut data)?;
+        return unpack(data, None);
+    }
+    let (file, entry) = split_entry(path);
+    unpack(fs::read(file)?, entry.as_deref())
+}
+
+/// The Octo options that go with a ROM file: the `.json` next to it with the same name.
+/// ROMs from standard input or archives have none.
+pub fn options_path(path: &Path) -> Option<PathBuf> {
+    if path == Path::new("-") || !path.is_file() {
+        return None;
+    }
+    Some(path.with_extension("json")).filter(|options| options.is_file() && options != path)
+}
+
+/// The file a ROM is read from, which is the archive for `archive.zip#name`
+pub fn source_file(path: &Path) -> PathBuf {
+    split_entry(path).0
+}
+
+/// `archive.zip#name` into the archive and the name of the entry,
+/// unless there is a file that really is called like that
+fn split_entry(path: &Path) -> (PathBuf, Option<String>) {
+    if !path.exists() {
+        if let Some((file, entry)) = path.to_str().and_then(|text| text.rsplit_once('#')) {
+            return (PathBuf::from(file), Some(entry.to_string()));
+        }
+    }
+    (path.to_path_buf(), None)
+}
+
+/// Get the ROM out of whatever it is packed in.
+/// A plain ROM can start like a packed file by chance (`1F8B` is `JP 0xF8B`),
+/// so data only counts as packed if it really unpacks.
+fn unpack(data: Vec<u8>, entry: Option<&str>) -> io::Result<Rom> {
+    if data.starts_with(GZIP_MAGIC) {
+        let mut unpacked = Vec::new();
+        if GzDecoder::new(data.as_slice()).read_to_end(&mut unpacked).is_ok() {
+            return unpack(unpacked, entry);
+        }
+    }
+    if data.starts_with(ZIP_MAGIC) {
+        if let Ok(archive) = ZipArchive::new(Cursor::new(data.as_slice())) {
+            return from_zip(archive, entry);
+        }
+    }
+    if entry.is_none() && data.starts_with(octo::GIF_MAGIC) && gif::DecodeOptions::new().read_info(data.as_slice()).is_ok() {
+        return from_cartridge(&data);
+    }
+    match entry {
+        Some(entry) => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("there is no {} in it, it is not a zip archive", entry),
+        )),
+        None => Ok(Rom { program: data, options: Config::default() }),
+    }
+}
+
+fn from_zip(mut archive: ZipArchive<Cursor<&[u8]>>, entry: Option<&str>) -> io::Result<Rom> {
+    let name = match entry {
+        Some(entry) => entry.to_string(),
+        None => {
+            let mut roms: Vec<String> = archive.file_names().filter(|name| is_rom(name)).map(str::to_string).collect();
+            roms.sort();
+            match roms.len() {
+                0 => return Err(io::Error::new(io::ErrorKind::NotFound, "there are no .ch8, .sc8, .xo8 or .gif files in it")),
+                1 => roms.remove(0),
+                _ => roms.remove(pick(&roms)?),
+            }
+        },
+    };
+
+    let mut file = archive
+        .by_name(&name)
+        .map_err(|_| io::Error::new(io::ErrorKind::NotFound, format!("there is no {} in it", name)))?;
+    let mut rom = Vec::new();
+    file.read_to_end(&mut rom)?;
+    // A compressed ROM in the archive
+    unpack(rom, None)
+}
+
+fn from_cartridge(gif: &[u8]) -> io::Result<Rom> {
+    let cartridge = octo::read_cartridge(gif)?;
+    let program = assembler::assemble(&cartridge.program)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("the program of the cartridge: {}", e)))?;
+    Ok(Rom { program, options: cartridge.options.to_config() })
+}
+
+/// `.ch8`, `.sc8`, `.xo8` or a cartridge, and the same with `.gz` on top
+fn is_rom(name: &str) -> bool {
+    let name = name.strip_suffix(".gz").unwrap_or(name);
+    Path::new(name)
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| ROM_EXTENSIONS.iter().any(|rom| rom.eq_ignore_ascii_case(extension)))
+}
+
+/// Let the user choose one of the ROMs in an archive
+fn pick(roms: &[String]) -> io::Result<usize> {
+    if !io::stdin().is_terminal() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("it has more than one ROM, pick one with #<name>: {}", roms.join(", ")),
+        ));
+    }
+
+    let mut stderr = io::stderr();
+    for (number, rom) in roms.iter().enumerate() {
+        writeln!(stderr, "{:>3}  {}", number + 1, rom)?;
+    }
+    loop {
+        write!(stderr, "which one? ")?;
+        stderr.flush()?;
+        let mut line = String::new();
+        if io::stdin().read_line(&mut line)? == 0 {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "no ROM picked"));
+        }
+        match line.trim().parse::<usize>() {
+            Ok(number) if (1..=roms.len()).contains(&number) => return Ok(number - 1),
+            _ => writeln!(stderr, "a number between 1 and {}", roms.len())?,
+        }
+    }
+}
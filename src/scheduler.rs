@@ -0,0 +1,105 @@
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::timing::{self, CYCLES_PER_FRAME, DISPLAY_CYCLES};
+use crate::machine::Machine;
+use crate::Cpu;
+
+/// The timers of the chip count down at 60 Hz, which is also the rate frames are shown at
+pub const FRAME_RATE: u32 = 60;
+
+/// A good default for most games, about 660 instructions a second
+pub const DEFAULT_IPF: u32 = 11;
+
+/// If the emulator falls behind by more frames than this (e.g. after the window was moved),
+/// they are dropped instead of racing to catch up
+const MAX_CATCH_UP: u32 = 4;
+
+/// How many instructions fit into a frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Timing {
+    /// The same number of instructions every frame, `ipf`
+    #[default]
+    Ipf,
+    /// As many as the VIP gets done in the time of a frame, every instruction takes as long as it did there
+    Vip,
+}
+
+/// Runs the chip in frames: the instructions of a frame,
+/// then the timers tick once
+pub struct Scheduler {
+    /// Instructions per frame
+    pub ipf: u32,
+    pub timing: Timing,
+    /// With the VIP timing, the machine cycles that are left for this frame.
+    /// An instruction that takes longer than what is left goes into the next one.
+    cycles: i64,
+    frame_time: Duration,
+    next_frame: Instant,
+}
+
+impl Scheduler {
+    pub fn new(ipf: u32, timing: Timing) -> Scheduler {
+        Scheduler {
+            ipf,
+            timing,
+            cycles: 0,
+            frame_time: Duration::from_secs(1) / FRAME_RATE,
+            next_frame: Instant::now(),
+        }
+    }
+
+    /// The time the next frame is due
+    pub fn next_frame(&self) -> Instant {
+        self.next_frame
+    }
+
+    /// Run a single frame of a `Cpu`, without looking at the clock.
+    /// Returns how many instructions ran, with the display wait quirk a drawing ends the frame early.
+    pub fn run_frame(&mut self, chip: &mut Cpu) -> u32 {
+        let mut instructions = 0;
+        match self.timing {
+            Timing::Ipf => {
+                while instructions < self.ipf && !chip.waiting_for_vblank {
+                    chip.execute_cycle();
+                    instructions += 1;
+                }
+            },
+            Timing::Vip => {
+                self.cycles += (CYCLES_PER_FRAME - DISPLAY_CYCLES) as i64;
+                while self.cycles > 0 && !chip.waiting_for_vblank {
+                    let (pc, opcode) = (chip.pc, chip.opcode());
+                    let vx = chip.v[((opcode & 0x0F00) >> 8) as usize];
+                    chip.execute_cycle();
+                    let skipped = chip.pc == pc.wrapping_add(4);
+                    self.cycles -= timing::vip_cycles(opcode, vx, skipped) as i64;
+                    instructions += 1;
+                }
+                // Waiting for the display doesn't save time for the next frame
+                self.cycles = self.cycles.min(0);
+            },
+        }
+        chip.tick_timers();
+        chip.waiting_for_vblank = false;
+        instructions
+    }
+
+    /// Run all frames that are due by now.
+    /// Returns true if at least one frame ran, so there is something new to show.
+    pub fn update(&mut self, machine: &mut dyn Machine) -> bool {
+        let now = Instant::now();
+        let mut frames = 0;
+        while self.next_frame <= now {
+            if frames == MAX_CATCH_UP {
+                self.next_frame = now + self.frame_time;
+                break;
+            }
+            machine.run_frame(self);
+            self.next_frame += self.frame_time;
+            frames += 1;
+        }
+        frames > 0
+    }
+}
@@ -0,0 +1,77 @@
+use serde::{Deserialize, Serialize};
+
+/// What happens when a program calls more subroutines than the stack has room for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StackOverflow {
+    /// The chip stops with an error
+    #[default]
+    Fault,
+    /// The oldest return address is overwritten, like the stack was a ring
+    Wrap,
+    /// The stack gets as deep as needed
+    Grow,
+}
+
+/// A subroutine call that hasn't returned yet
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Frame {
+    /// The address of the subroutine
+    pub subroutine: u16,
+    /// Where the program goes on after the subroutine, right after the call
+    pub return_address: u16,
+}
+
+/// The calls the program is in, the innermost last
+#[derive(Debug, Clone)]
+pub struct Stack {
+    frames: Vec<Frame>,
+    /// How many calls there is room for
+    pub depth: usize,
+    pub overflow: StackOverflow,
+}
+
+impl Stack {
+    pub fn new(depth: usize, overflow: StackOverflow) -> Stack {
+        Stack {
+            frames: Vec::with_capacity(depth),
+            depth,
+            overflow,
+        }
+    }
+
+    /// Put a call on top, false if there is no room for it
+    pub fn push(&mut self, frame: Frame) -> bool {
+        if self.frames.len() >= self.depth {
+            match self.overflow {
+                StackOverflow::Fault => return false,
+                StackOverflow::Wrap => {
+                    self.frames.remove(0);
+                },
+                StackOverflow::Grow => (),
+            }
+        }
+        self.frames.push(frame);
+        true
+    }
+
+    /// Take the innermost call, none if the program returned from more calls than it made
+    pub fn pop(&mut self) -> Option<Frame> {
+        self.frames.pop()
+    }
+
+    /// The calls from the outermost to the innermost
+    pub fn frames(&self) -> &[Frame] {
+        &self.frames
+    }
+
+    pub fn clear(&mut self) {
+        self.frames.clear();
+    }
+}
+
+impl Default for Stack {
+    fn default() -> Stack {
+        Stack::new(16, StackOverflow::default())
+    }
+}
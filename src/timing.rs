@@ -0,0 +1,62 @@
+/// Clock of the VIP
+const CLOCK: u32 = 1_760_640;
+
+/// Machine cycles in a frame of 60 Hz
+pub const CYCLES_PER_FRAME: u32 = CLOCK / 8 / 60;
+
+/// Cycles the CDP1861 takes away for showing the display: one for every byte
+/// of the 32 rows that are shown 4 times each, and the interrupt routine around it
+pub const DISPLAY_CYCLES: u32 = 32 * 4 * 8 + 46;
+
+/// Fetching and decoding an instruction, which every instruction takes on top
+const FETCH: u32 = 40;
+
+/// How long the CHIP-8 interpreter of the COSMAC VIP takes for an instruction,
+/// in machine cycles of its CDP1802 (8 clock cycles each).
+/// The numbers are estimates after the disassembly of the interpreter by Laurence Scotford
+/// (<https://laurencescotford.net>, "Chip-8 on the COSMAC VIP"), close enough
+/// for games to run at the right speed, but not exact to the cycle.
+/// `vx` is the value of Vx before the instruction ran, `skipped` whether it skipped the next one.
+pub fn vip_cycles(opcode: u16, vx: u8, skipped: bool) -> u32 {
+    let n = (opcode & 0x000F) as u32;
+    let skip = |cycles: u32| if skipped { cycles + 4 } else { cycles };
+
+    let op_1 = (opcode & 0xF000) >> 12;
+    let op_4 = opcode & 0x000F;
+    FETCH + match (op_1, opcode & 0x00FF) {
+        // Clears all 256 bytes of the display memory in a loop
+        (0x0, 0xE0) => 3038,
+        (0x0, 0xEE) => 10,
+        // The routine itself isn't counted
+        (0x0, _) => 10,
+        (0x1, _) => 12,
+        (0x2, _) => 26,
+        (0x3, _) | (0x4, _) => skip(10),
+        (0x5, _) | (0x9, _) => skip(14),
+        (0x6, _) => 6,
+        (0x7, _) => 10,
+        // 8xy0 is a plain copy, the others go through a routine that is built in memory
+        (0x8, _) if op_4 == 0 => 12,
+        (0x8, _) => 44,
+        (0xA, _) => 12,
+        (0xB, _) => 22,
+        (0xC, _) => 36,
+        // Every row is shifted into place bit by bit, then both bytes it covers are drawn
+        (0xD, _) => {
+            let shift = (vx & 7) as u32;
+            26 + n * (28 + 4 * shift) + if shift == 0 { 0 } else { n * 14 }
+        },
+        (0xE, _) => skip(14),
+        (0xF, 0x07) | (0xF, 0x15) | (0xF, 0x18) => 10,
+        // Once for every time the key is looked at
+        (0xF, 0x0A) => 18,
+        (0xF, 0x1E) | (0xF, 0x29) => 16,
+        // The digits are found by subtracting 100 and 10 over and over
+        (0xF, 0x33) => {
+            let digits = (vx / 100 + vx / 10 % 10 + vx % 10) as u32;
+            80 + 16 * digits
+        },
+        (0xF, 0x55) | (0xF, 0x65) => 14 + 14 * (((opcode & 0x0F00) >> 8) as u32 + 1),
+        _ => 10,
+    }
+}
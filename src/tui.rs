@@ -0,0 +1,345 @@
+use std::io::{self, Stdout, Write};
+use std::time::{Duration, Instant};
+
+use crossterm::event::{
+    self, Event, KeyCode, KeyEvent, KeyEventKind, KeyEventState, KeyModifiers, KeyboardEnhancementFlags,
+    PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags,
+};
+use crossterm::style::{self, Print, ResetColor, SetBackgroundColor, SetForegroundColor};
+use crossterm::terminal::{self, ClearType};
+use crossterm::{cursor, execute, queue};
+use winit::event::{ElementState, VirtualKeyCode};
+
+use crate::config::Settings;
+use crate::keymap;
+use crate::machine::Machine;
+use crate::palette::{Color, Palette, Theme};
+use crate::scheduler::Scheduler;
+use crate::{Display, Fault, Loaded, Rotation, Session, State};
+
+/// How the pixels are put into the cells of the terminal
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum Glyphs {
+    /// Two pixels a cell, one above the other, each in its own color
+    #[default]
+    Half,
+    /// 2x4 pixels a cell as braille dots, half as wide but only one color a cell
+    Braille,
+}
+
+/// Without the kitty keyboard protocol the terminal doesn't tell when a key is let go,
+/// a key counts as released when it wasn't repeated for a while.
+/// The first repeat only comes after the repeat delay of the keyboard
+const FIRST_HOLD: Duration = Duration::from_millis(500);
+const REPEAT_HOLD: Duration = Duration::from_millis(100);
+
+/// The character of a cell, its foreground and its background color
+type Cell = (char, Color, Color);
+
+/// The terminal in raw mode on the alternate screen, set back the way it was when dropped
+struct Screen {
+    out: Stdout,
+    /// Whether the terminal speaks the kitty keyboard protocol and reports released keys
+    releases: bool,
+}
+
+impl Screen {
+    fn open() -> io::Result<Screen> {
+        terminal::enable_raw_mode()?;
+        let mut out = io::stdout();
+        execute!(out, terminal::EnterAlternateScreen, cursor::Hide, terminal::Clear(ClearType::All))?;
+        let releases = terminal::supports_keyboard_enhancement().unwrap_or(false);
+        if releases {
+            let flags = KeyboardEnhancementFlags::DISAMBIGUATE_ESCAPE_CODES | KeyboardEnhancementFlags::REPORT_EVENT_TYPES;
+            execute!(out, PushKeyboardEnhancementFlags(flags))?;
+        }
+        Ok(Screen { out, releases })
+    }
+}
+
+impl Drop for Screen {
+    fn drop(&mut self) {
+        if self.releases {
+            let _ = execute!(self.out, PopKeyboardEnhancementFlags);
+        }
+        let _ = execute!(self.out, ResetColor, cursor::Show, terminal::LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+/// What is on the screen, to only redraw the cells that changed
+struct View {
+    glyphs: Glyphs,
+    palette: Palette,
+    theme: Theme,
+    rotation: Rotation,
+    cells: Vec<Cell>,
+    columns: usize,
+    /// Shown below the display: why the chip stopped or what went wrong
+    status: String,
+    shown_status: Option<String>,
+}
+
+impl View {
+    /// Draw everything again, after the terminal was resized or written over
+    fn invalidate(&mut self) {
+        self.cells.clear();
+        self.shown_status = None;
+    }
+
+    fn draw(&mut self, out: &mut impl Write, display: &Display) -> io::Result<()> {
+        let (cells, columns) = match self.glyphs {
+            Glyphs::Half => half_blocks(display, &self.palette, self.rotation),
+            Glyphs::Braille => braille(display, &self.palette, self.rotation),
+        };
+        let rows = cells.len() / columns;
+        if columns != self.columns || cells.len() != self.cells.len() {
+            self.columns = columns;
+            self.invalidate();
+            queue!(out, ResetColor, terminal::Clear(ClearType::All))?;
+        }
+
+        // What doesn't fit into the terminal is cut off
+        let (width, height) = terminal::size()?;
+        let mut colors = None;
+        for (index, cell) in cells.iter().enumerate() {
+            let (column, row) = (index % columns, index / columns);
+            if self.cells.get(index) == Some(cell) || column >= width as usize || row >= height as usize {
+                continue;
+            }
+            queue!(out, cursor::MoveTo(column as u16, row as u16))?;
+            if colors != Some((cell.1, cell.2)) {
+                queue!(out, SetForegroundColor(to_terminal_color(cell.1)), SetBackgroundColor(to_terminal_color(cell.2)))?;
+                colors = Some((cell.1, cell.2));
+            }
+            queue!(out, Print(cell.0))?;
+        }
+        self.cells = cells;
+
+        if self.shown_status.as_ref() != Some(&self.status) {
+            queue!(out, ResetColor, cursor::MoveTo(0, rows as u16), terminal::Clear(ClearType::FromCursorDown))?;
+            // Raw mode doesn't go back to the start of the line
+            for (index, line) in self.status.lines().enumerate() {
+                queue!(out, cursor::MoveTo(0, (rows + index) as u16), Print(line))?;
+            }
+            self.shown_status = Some(self.status.clone());
+        }
+        out.flush()
+    }
+}
+
+/// Run the ROM in the terminal until Escape or Ctrl+C is pressed.
+/// The keys are the same as in the window, minus recording, phosphor and fullscreen
+pub fn run(mut chip: Box<dyn Machine>, mut scheduler: Scheduler, mut session: Session, settings: &Settings, glyphs: Glyphs) -> io::Result<()> {
+    let mut screen = Screen::open()?;
+    let mut view = View {
+        glyphs,
+        palette: settings.palette,
+        theme: settings.theme,
+        rotation: settings.rotation,
+        cells: Vec::new(),
+        columns: 0,
+        status: String::new(),
+        shown_status: None,
+    };
+    // The keys that are down and when they count as released, without the kitty protocol
+    let mut held: Vec<(VirtualKeyCode, Instant)> = Vec::new();
+    // The state that was last told about
+    let mut shown = State::Running;
+
+    loop {
+        // Wait for keys until the next frame is due
+        let timeout = scheduler.next_frame().saturating_duration_since(Instant::now());
+        if event::poll(timeout)? {
+            match event::read()? {
+                Event::Key(KeyEvent { code, modifiers, kind, state }) => {
+                    let pressed = kind == KeyEventKind::Press;
+                    match code {
+                        KeyCode::Esc if pressed => return Ok(()),
+                        // Raw mode turns Ctrl+C into a key like any other
+                        KeyCode::Char('c') if pressed && modifiers.contains(KeyModifiers::CONTROL) => return Ok(()),
+                        // F4 cycles through the built in color themes
+                        KeyCode::F(4) if pressed => {
+                            view.theme = view.theme.next();
+                            view.palette = view.theme.palette();
+                        },
+                        // F5 starts the program over, F6 also reloads it into cleared memory
+                        KeyCode::F(5) if pressed => chip.soft_reset(),
+                        KeyCode::F(6) if pressed => chip.hard_reset(),
+                        // F8 pauses the chip and lets it go on
+                        KeyCode::F(8) if pressed => chip.toggle_pause(),
+                        _ => {
+                            if let Some(key) = virtual_key(code, state) {
+                                let index = held.iter().position(|(held_key, _)| *held_key == key);
+                                if let Some(index) = index {
+                                    held.remove(index);
+                                }
+                                if kind == KeyEventKind::Release {
+                                    chip.keypad().keypad_input(key, ElementState::Released);
+                                } else {
+                                    chip.keypad().keypad_input(key, ElementState::Pressed);
+                                    if !screen.releases {
+                                        // A key that is held down gets repeated
+                                        let hold = if index.is_some() { REPEAT_HOLD } else { FIRST_HOLD };
+                                        held.push((key, Instant::now() + hold));
+                                    }
+                                }
+                            }
+                        },
+                    }
+                },
+                Event::Resize(..) => view.invalidate(),
+                _ => (),
+            }
+        }
+
+        let now = Instant::now();
+        held.retain(|&(key, until)| {
+            if until <= now {
+                chip.keypad().keypad_input(key, ElementState::Released);
+            }
+            until > now
+        });
+
+        // In --watch mode the ROM starts over whenever it changes
+        if session.changed() {
+            match session.load() {
+                Ok(Loaded { chip: loaded, settings }) => {
+                    chip = loaded;
+                    scheduler.ipf = settings.ipf;
+                    scheduler.timing = settings.timing;
+                    view.rotation = settings.rotation;
+                    // Like in the window, a palette of the ROM wins over the theme
+                    let custom = |palette: Palette, theme: Theme| palette != theme.palette();
+                    if custom(settings.palette, settings.theme) || custom(view.palette, view.theme) {
+                        view.palette = settings.palette;
+                        view.theme = settings.theme;
+                    }
+                    view.status.clear();
+                },
+                Err(e) => view.status = format!("error: {}", e),
+            }
+            // Loading prints the title over the screen
+            view.invalidate();
+        }
+
+        if scheduler.update(chip.as_mut()) || view.cells.is_empty() {
+            // Tell once when the chip stops
+            if chip.state() != shown {
+                view.status = match chip.state() {
+                    State::Halted(fault @ Fault::Exit { .. }) => format!("{}, F5 or F6 starts over", fault),
+                    State::Halted(fault) => format!("error: {}, F5 or F6 starts over", fault),
+                    State::Paused(Some(fault)) => format!("paused: {}, F8 goes on\n{}", fault, chip.describe()),
+                    State::Paused(None) => format!("paused, F8 goes on\n{}", chip.describe()),
+                    State::Running => String::new(),
+                };
+                shown = chip.state();
+            }
+            // Printed they would end up on top of the display
+            for warning in chip.take_warnings() {
+                let line = format!("warning: {}", warning);
+                // A flags file that can't be written fails every time
+                if view.status.lines().any(|shown| shown == line) {
+                    continue;
+                }
+                if !view.status.is_empty() {
+                    view.status.push('\n');
+                }
+                view.status += &line;
+            }
+            view.draw(&mut screen.out, chip.display())?;
+        }
+    }
+}
+
+/// Two pixels on top of each other in a cell, the upper half block in the color of the upper one
+/// on the color of the lower one. Returns the cells and how many there are in a row
+fn half_blocks(display: &Display, palette: &Palette, rotation: Rotation) -> (Vec<Cell>, usize) {
+    let color = |x, y| pixel(display, palette, rotation, x, y);
+    let (width, height) = rotation.size((display.width(), display.height()));
+    let mut cells = Vec::with_capacity(width * height.div_ceil(2));
+    for y in (0..height).step_by(2) {
+        for x in 0..width {
+            let upper = color(x, y).0;
+            let lower = if y + 1 < height { color(x, y + 1).0 } else { palette.color(0) };
+            cells.push(('▀', upper, lower));
+        }
+    }
+    (cells, width)
+}
+
+/// The bits of the braille dots for the 2x4 pixels of a cell, by row and column
+const DOTS: [[u32; 2]; 4] = [[0x01, 0x08], [0x02, 0x10], [0x04, 0x20], [0x40, 0x80]];
+
+/// 2x4 pixels in a cell as braille dots for the set pixels. A cell has only two colors,
+/// the dots take the color of the first set pixel, the rest the color of the first pixel that isn't
+fn braille(display: &Display, palette: &Palette, rotation: Rotation) -> (Vec<Cell>, usize) {
+    let (width, height) = rotation.size((display.width(), display.height()));
+    let columns = width.div_ceil(2);
+    let mut cells = Vec::with_capacity(columns * height.div_ceil(4));
+    for top in (0..height).step_by(4) {
+        for left in (0..width).step_by(2) {
+            let (mut dots, mut foreground, mut background) = (0, None, None);
+            for (row, bits) in DOTS.iter().enumerate() {
+                for (column, bit) in bits.iter().enumerate() {
+                    let (x, y) = (left + column, top + row);
+                    if x >= width || y >= height {
+                        continue;
+                    }
+                    match pixel(display, palette, rotation, x, y) {
+                        (color, true) => {
+                            dots |= bit;
+                            foreground.get_or_insert(color);
+                        },
+                        (color, false) => {
+                            background.get_or_insert(color);
+                        },
+                    }
+                }
+            }
+            let background = background.unwrap_or(palette.color(0));
+            let glyph = char::from_u32(0x2800 + dots).unwrap_or(' ');
+            cells.push((glyph, foreground.unwrap_or(background), background));
+        }
+    }
+    (cells, columns)
+}
+
+/// The color of `x`, `y` of the turned picture and whether the pixel is set
+fn pixel(display: &Display, palette: &Palette, rotation: Rotation, x: usize, y: usize) -> (Color, bool) {
+    let (x, y) = rotation.source(x, y, (display.width(), display.height()));
+    display.color(x, y, palette)
+}
+
+fn to_terminal_color(color: Color) -> style::Color {
+    style::Color::Rgb { r: color[0], g: color[1], b: color[2] }
+}
+
+/// The key of the window that a key of the terminal is, so the keymaps work the same.
+/// Only terminals with the kitty protocol tell the number block apart
+fn virtual_key(code: KeyCode, state: KeyEventState) -> Option<VirtualKeyCode> {
+    use VirtualKeyCode::*;
+    if state.contains(KeyEventState::KEYPAD) {
+        return match code {
+            KeyCode::Char(digit @ '0'..='9') => keymap::key_from_name(&format!("numpad{}", digit)),
+            KeyCode::Char('/') => Some(NumpadDivide),
+            KeyCode::Char('*') => Some(NumpadMultiply),
+            KeyCode::Char('-') => Some(NumpadSubtract),
+            KeyCode::Char('+') => Some(NumpadAdd),
+            KeyCode::Char('.') | KeyCode::Char(',') => Some(NumpadDecimal),
+            KeyCode::Enter => Some(NumpadEnter),
+            _ => None,
+        };
+    }
+    match code {
+        KeyCode::Char(' ') => Some(Space),
+        KeyCode::Char(c) => keymap::key_from_name(&c.to_string()),
+        KeyCode::Up => Some(Up),
+        KeyCode::Down => Some(Down),
+        KeyCode::Left => Some(Left),
+        KeyCode::Right => Some(Right),
+        KeyCode::Enter => Some(Return),
+        KeyCode::Tab => Some(Tab),
+        _ => None,
+    }
+}
@@ -0,0 +1,290 @@
+use crate::cdp1802::{Bus, Cdp1802};
+use crate::machine::Machine;
+use crate::recorder::Sound;
+use crate::scheduler::Scheduler;
+use crate::{Display, Keypad, State};
+
+/// The monitor ROM is at 0x8000 and repeats up to the end of the address space
+const MONITOR_ADDRESS: u16 = 0x8000;
+pub const MONITOR_SIZE: usize = 0x200;
+
+/// The CDP1861 shows 262 lines a frame, every line takes 14 machine cycles
+const LINES: u32 = 262;
+const LINE_CYCLES: i32 = 14;
+/// The 128 lines that are shown, every one takes 8 bytes of memory by DMA
+const FIRST_LINE: u32 = 80;
+const SHOWN_LINES: u32 = 128;
+/// The interrupt comes two lines before the display starts, so the program can set up R0
+const INTERRUPT_LINE: u32 = 78;
+/// EF1 is asserted for the 4 lines before the display and the last 4 lines of it
+const EF1_LINES: [std::ops::Range<u32>; 2] = [76..80, 204..208];
+
+/// Everything around the CDP1802: memory, the CDP1861 video chip and the keypad
+struct Hardware {
+    ram: Vec<u8>,
+    monitor: Option<Vec<u8>>,
+    /// After a reset the monitor shows up at 0 too, until the first address with A15 set
+    monitor_at_zero: bool,
+    /// The key the keypad decoder is set to with `OUT 2`
+    key: u8,
+    display_on: bool,
+    /// The line the CDP1861 is at
+    line: u32,
+    keypad: Keypad,
+}
+
+impl Hardware {
+    fn monitor_byte(&self, address: u16) -> Option<u8> {
+        self.monitor.as_ref().map(|monitor| monitor[address as usize % MONITOR_SIZE])
+    }
+}
+
+impl Bus for Hardware {
+    fn read(&mut self, address: u16) -> u8 {
+        if address >= MONITOR_ADDRESS {
+            self.monitor_at_zero = false;
+            return self.monitor_byte(address).unwrap_or(0xFF);
+        }
+        if self.monitor_at_zero {
+            if let Some(byte) = self.monitor_byte(address) {
+                return byte;
+            }
+        }
+        // Memory that isn't there repeats the memory that is
+        self.ram[address as usize & (self.ram.len() - 1)]
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        if address >= MONITOR_ADDRESS {
+            self.monitor_at_zero = false;
+            return;
+        }
+        let size = self.ram.len();
+        self.ram[address as usize & (size - 1)] = value;
+    }
+
+    fn output(&mut self, port: u8, value: u8) {
+        match port {
+            1 => self.display_on = false,
+            2 => self.key = value & 0xF,
+            _ => (),
+        }
+    }
+
+    fn input(&mut self, port: u8) -> u8 {
+        if port == 1 {
+            self.display_on = true;
+        }
+        0xFF
+    }
+
+    fn flag(&self, line: u8) -> bool {
+        match line {
+            1 => self.display_on && EF1_LINES.iter().any(|lines| lines.contains(&self.line)),
+            3 => self.keypad.is_key_down(self.key),
+            _ => false,
+        }
+    }
+}
+
+/// A COSMAC VIP that runs its own CHIP-8 interpreter, which runs the program.
+/// The timers, the random numbers and all quirks come from the interpreter,
+/// only the keymap of the settings is used.
+pub struct CosmacVip {
+    pub cpu: Cdp1802,
+    hardware: Hardware,
+    display: Display,
+    /// Loaded at 0 on a hard reset
+    interpreter: Vec<u8>,
+    program: Vec<u8>,
+    load_address: u16,
+    /// The registers have a random value after power on, R9 (which the interpreter takes
+    /// its random numbers from) gets this one
+    seed: u64,
+    state: State,
+    /// Machine cycles left over from the last line, below 0 if an instruction took longer
+    cycles: i32,
+}
+
+impl CosmacVip {
+    /// `memory_size` is the RAM, a power of two up to 32K.
+    /// Without the monitor ROM the VIP starts right at the interpreter, the way the monitor
+    /// hands over to it with R1 pointing at the last page of RAM.
+    /// The lines are shown on the rows of `display`, 4 lines a row for 32 rows (2 for 64).
+    pub fn new(memory_size: usize, interpreter: Vec<u8>, monitor: Option<Vec<u8>>, display: Display) -> CosmacVip {
+        let mut vip = CosmacVip {
+            cpu: Cdp1802::new(),
+            hardware: Hardware {
+                ram: vec![0; memory_size],
+                monitor,
+                monitor_at_zero: true,
+                key: 0,
+                display_on: false,
+                line: 0,
+                keypad: Keypad::new(),
+            },
+            display,
+            interpreter,
+            program: Vec::new(),
+            load_address: 0x200,
+            seed: 0,
+            state: State::Running,
+            cycles: 0,
+        };
+        vip.hard_reset();
+        vip
+    }
+
+    pub fn seed(&mut self, seed: u64) {
+        self.seed = seed;
+    }
+
+    /// Put the program into memory behind the interpreter and start it.
+    /// It has to fit into the RAM without touching the interpreter or the reserved ranges.
+    pub fn load_program(&mut self, load_address: u16, reserved: &[(u16, u16)], program: &[u8]) -> Result<(), String> {
+        let start = load_address as usize;
+        let end = start + program.len();
+        let overlaps = |first: usize, last: usize| start <= last && end > first;
+        if end > self.hardware.ram.len() {
+            return Err(format!(
+                "the program is {} bytes too big, the RAM of the VIP ends at {:#05X}",
+                end - self.hardware.ram.len(),
+                self.hardware.ram.len() - 1
+            ));
+        }
+        if start < self.interpreter.len() {
+            return Err(format!("the program would overwrite the interpreter, which ends at {:#05X}", self.interpreter.len() - 1));
+        }
+        if let Some((first, last)) = reserved.iter().find(|(first, last)| overlaps(*first as usize, *last as usize)) {
+            return Err(format!(
+                "the program ({:#05X} - {:#05X}) overlaps the memory of the interpreter at {:#05X} - {:#05X}",
+                start, end.saturating_sub(1), first, last
+            ));
+        }
+        self.load_address = load_address;
+        self.program = program.to_vec();
+        self.hard_reset();
+        Ok(())
+    }
+
+    /// Show the 8 bytes of a line, the interpreter shows every row of the display on several lines
+    fn show_line(&mut self, line: u32, bytes: &[u8; 8]) {
+        let row = (line - FIRST_LINE) as usize * self.display.height() / SHOWN_LINES as usize;
+        for (column, byte) in bytes.iter().enumerate() {
+            for bit in 0..8 {
+                self.display.set_pixel(column * 8 + bit, row, byte >> (7 - bit) & 1 == 1);
+            }
+        }
+    }
+}
+
+impl Machine for CosmacVip {
+    /// A frame of the CDP1861, with the time the DMA takes away from the program.
+    /// The instructions per frame and the timing of the scheduler don't matter,
+    /// the VIP runs as fast as it did
+    fn run_frame(&mut self, _scheduler: &mut Scheduler) -> u32 {
+        if self.state != State::Running {
+            return 0;
+        }
+        let mut instructions = 0;
+        for line in 0..LINES {
+            self.hardware.line = line;
+            self.cycles += LINE_CYCLES;
+            if self.hardware.display_on {
+                if line == INTERRUPT_LINE {
+                    self.cpu.interrupt();
+                }
+                if (FIRST_LINE..FIRST_LINE + SHOWN_LINES).contains(&line) {
+                    let mut bytes = [0; 8];
+                    for byte in &mut bytes {
+                        *byte = self.cpu.dma_out(&mut self.hardware);
+                    }
+                    self.show_line(line, &bytes);
+                    self.cycles -= bytes.len() as i32;
+                }
+            }
+            while self.cycles > 0 {
+                if !self.cpu.idle {
+                    instructions += 1;
+                }
+                self.cycles -= self.cpu.step(&mut self.hardware) as i32;
+            }
+        }
+        if !self.hardware.display_on {
+            self.display.cls();
+        }
+        instructions
+    }
+
+    fn display(&self) -> &Display {
+        &self.display
+    }
+
+    fn keypad(&mut self) -> &mut Keypad {
+        &mut self.hardware.keypad
+    }
+
+    /// Q turns the tone on
+    fn sound(&self) -> Sound<'_> {
+        if self.cpu.q { Sound::Beep } else { Sound::Silent }
+    }
+
+    /// Like the reset switch, the memory stays and so does the program in it
+    fn soft_reset(&mut self) {
+        self.cpu.reset();
+        self.hardware.monitor_at_zero = true;
+        self.hardware.display_on = false;
+        self.state = State::Running;
+        self.cycles = 0;
+        self.display.cls();
+        if self.hardware.monitor.is_none() {
+            let last_page = (self.hardware.ram.len() / 0x100 - 1) as u16;
+            self.cpu.r[1] = last_page << 8;
+        }
+    }
+
+    fn hard_reset(&mut self) {
+        self.cpu = Cdp1802::new();
+        self.cpu.r[9] = self.seed as u16;
+        let ram = &mut self.hardware.ram;
+        ram.fill(0);
+        let length = self.interpreter.len().min(ram.len());
+        ram[..length].copy_from_slice(&self.interpreter[..length]);
+        let start = self.load_address as usize;
+        ram[start..start + self.program.len()].copy_from_slice(&self.program);
+        self.soft_reset();
+    }
+
+    fn toggle_pause(&mut self) {
+        self.state = match self.state {
+            State::Running => State::Paused(None),
+            _ => State::Running,
+        };
+    }
+
+    fn state(&self) -> State {
+        self.state
+    }
+
+    /// The registers of the CDP1802
+    fn describe(&self) -> String {
+        let cpu = &self.cpu;
+        let mut text = format!(
+            "  p {:X}  x {:X}  d {:02X}  df {}  q {}  ie {}\n ",
+            cpu.p, cpu.x, cpu.d, cpu.df as u8, cpu.q as u8, cpu.ie as u8
+        );
+        for (index, value) in cpu.r.iter().enumerate() {
+            text += &format!(" r{:X} {:04X}", index, value);
+            if index == 7 {
+                text += "\n ";
+            }
+        }
+        text += "\n";
+        text
+    }
+
+    /// The interpreter runs as it is, nothing is found wrong on the way
+    fn take_warnings(&mut self) -> Vec<String> {
+        Vec::new()
+    }
+}
@@ -0,0 +1,89 @@
+use crate::palette::Color;
+
+/// The foreground colors of the VP-590, by the 3 bits of red, blue and green
+const FOREGROUNDS: [Color; 8] = [
+    [0x00, 0x00, 0x00, 0xFF],
+    [0xFF, 0x00, 0x00, 0xFF],
+    [0x00, 0x00, 0xFF, 0xFF],
+    [0xFF, 0x00, 0xFF, 0xFF],
+    [0x00, 0xFF, 0x00, 0xFF],
+    [0xFF, 0xFF, 0x00, 0xFF],
+    [0x00, 0xFF, 0xFF, 0xFF],
+    [0xFF, 0xFF, 0xFF, 0xFF],
+];
+
+/// The background colors `02A0` steps through
+const BACKGROUNDS: [Color; 4] = [
+    [0x00, 0x00, 0x80, 0xFF],
+    [0x00, 0x00, 0x00, 0xFF],
+    [0x00, 0x80, 0x00, 0xFF],
+    [0x80, 0x00, 0x00, 0xFF],
+];
+
+/// Red, the color of the pixels that weren't colored
+const DEFAULT_FOREGROUND: u8 = 1;
+
+/// Columns of 8 pixels and rows of 4 pixels that `Bxy0` colors
+const COLUMNS: usize = 8;
+const ZONE_HEIGHT: usize = 4;
+
+/// The VP-590 color board of CHIP-8X.
+/// The display stays black and white, the board decides the color of the pixels that are set
+/// for every 8 pixels of a row, and the one color of all others.
+#[derive(Debug, Clone)]
+pub struct ColorBoard {
+    background: usize,
+    /// The foreground color of every 8 pixels of every row
+    foregrounds: Vec<u8>,
+    height: usize,
+}
+
+impl ColorBoard {
+    pub fn new(height: usize) -> ColorBoard {
+        ColorBoard {
+            background: 0,
+            foregrounds: vec![DEFAULT_FOREGROUND; COLUMNS * height],
+            height,
+        }
+    }
+
+    /// `02A0`, the next background color
+    pub fn next_background(&mut self) {
+        self.background = (self.background + 1) % BACKGROUNDS.len();
+    }
+
+    /// `Bxy0`, color zones of 8x4 pixels. `horizontal` has the first column of zones in its low nibble
+    /// and how many more there are in its high nibble, `vertical` the same for the rows of zones
+    pub fn color_zones(&mut self, horizontal: u8, vertical: u8, color: u8) {
+        let columns = (horizontal & 0xF) as usize..=((horizontal & 0xF) + (horizontal >> 4)) as usize;
+        let first_row = (vertical & 0xF) as usize * ZONE_HEIGHT;
+        let rows = first_row..first_row + ((vertical >> 4) as usize + 1) * ZONE_HEIGHT;
+        for row in rows {
+            for column in columns.clone() {
+                self.set(column, row, color);
+            }
+        }
+    }
+
+    /// `Bxyn` with n other than 0, color `n` rows of the 8 pixels at `x`, `y` and below
+    pub fn color_rows(&mut self, x: u8, y: u8, n: u8, color: u8) {
+        let column = x as usize / 8 % COLUMNS;
+        for row in y as usize..y as usize + n as usize {
+            self.set(column, row, color);
+        }
+    }
+
+    /// The color of a pixel, `lit` if it is set in the display
+    pub fn color(&self, x: usize, y: usize, lit: bool) -> Color {
+        if lit {
+            FOREGROUNDS[self.foregrounds[x / 8 % COLUMNS + y % self.height * COLUMNS] as usize]
+        } else {
+            BACKGROUNDS[self.background]
+        }
+    }
+
+    /// What is past the edges wraps around
+    fn set(&mut self, column: usize, row: usize, color: u8) {
+        self.foregrounds[column % COLUMNS + row % self.height * COLUMNS] = color & 7;
+    }
+}
@@ -0,0 +1,44 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+/// How often the files are looked at
+const INTERVAL: Duration = Duration::from_millis(250);
+
+/// Notices when files change, by looking at their modification time now and then
+pub struct Watcher {
+    files: Vec<(PathBuf, Option<SystemTime>)>,
+    checked: Instant,
+}
+
+impl Watcher {
+    pub fn new(paths: &[PathBuf]) -> Watcher {
+        Watcher {
+            files: paths.iter().map(|path| (path.clone(), modified(path))).collect(),
+            checked: Instant::now(),
+        }
+    }
+
+    /// True once after one of the files changed.
+    /// A file that is gone (while an editor writes it) doesn't count as changed.
+    pub fn changed(&mut self) -> bool {
+        if self.checked.elapsed() < INTERVAL {
+            return false;
+        }
+        self.checked = Instant::now();
+
+        let mut changed = false;
+        for (path, last) in &mut self.files {
+            let now = modified(path);
+            if now.is_some() && now != *last {
+                *last = now;
+                changed = true;
+            }
+        }
+        changed
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}